@@ -0,0 +1,152 @@
+use crate::Error;
+use git2::{ErrorCode, Oid, Repository};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+// Bitset of branch tips, indexed by the position of the tip in the sorted list of tips.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TipSet(Vec<u64>);
+
+impl TipSet {
+    fn new(len: usize) -> Self {
+        TipSet(vec![0; len.div_ceil(64)])
+    }
+
+    fn full(len: usize) -> Self {
+        let mut set = TipSet::new(len);
+        for i in 0..len {
+            set.insert(i);
+        }
+        set
+    }
+
+    fn insert(&mut self, i: usize) {
+        self.0[i / 64] |= 1 << (i % 64);
+    }
+
+    fn contains(&self, i: usize) -> bool {
+        self.0[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    // Adds all tips of other to this set, returns true if the set changed.
+    fn union_with(&mut self, other: &TipSet) -> bool {
+        let mut changed = false;
+        for (word, other_word) in self.0.iter_mut().zip(&other.0) {
+            let merged = *word | *other_word;
+            changed |= merged != *word;
+            *word = merged;
+        }
+        changed
+    }
+}
+
+// Ancestry relationships between a set of branch tips.
+//
+// Instead of computing a merge base for every pair of tips, the commit graph is walked once
+// from all tips at the same time, newest commits first, and each commit is painted with the
+// set of tips it is reachable from. Once a tip has been painted, it is known to be an ancestor
+// of exactly the tips in its set.
+//
+// The walk stops as soon as every commit left to visit is reachable from all tips, since nothing
+// below that point can change the relationships between tips.
+//
+// With first_parent, only the first parent of merge commits is followed, like git log --first-parent.
+pub(crate) struct TipAncestry {
+    index: HashMap<Oid, usize>,
+    reachable_from: Vec<TipSet>,
+}
+
+impl TipAncestry {
+    pub(crate) fn compute(
+        repo: &Repository,
+        tips: impl IntoIterator<Item = Oid>,
+        first_parent: bool,
+    ) -> Result<Self, Error> {
+        let mut tips: Vec<Oid> = tips.into_iter().collect();
+        tips.sort();
+        tips.dedup();
+
+        let index: HashMap<Oid, usize> =
+            tips.iter().enumerate().map(|(i, oid)| (*oid, i)).collect();
+        let full = TipSet::full(tips.len());
+
+        let mut paint: HashMap<Oid, TipSet> = HashMap::new();
+        let mut queue: BinaryHeap<(i64, Oid)> = BinaryHeap::new();
+        let mut queued: HashSet<Oid> = HashSet::new();
+        // Number of queued commits not yet reachable from all tips
+        let mut pending = 0usize;
+
+        for (i, oid) in tips.iter().enumerate() {
+            let commit = repo.find_commit(*oid)?;
+            let mut set = TipSet::new(tips.len());
+            set.insert(i);
+            if set != full {
+                pending += 1;
+            }
+            paint.insert(*oid, set);
+            queue.push((commit.time().seconds(), *oid));
+            queued.insert(*oid);
+        }
+
+        while pending > 0 {
+            let Some((_, oid)) = queue.pop() else {
+                break;
+            };
+            queued.remove(&oid);
+
+            let set = paint[&oid].clone();
+            if set != full {
+                pending -= 1;
+            }
+
+            let commit = repo.find_commit(oid)?;
+            let num_parents = if first_parent { 1 } else { usize::MAX };
+            for parent_oid in commit.parent_ids().take(num_parents) {
+                let parent_commit = match repo.find_commit(parent_oid) {
+                    Ok(parent_commit) => parent_commit,
+                    // Missing parents happen in shallow clones, there's nothing to walk past them
+                    Err(e) if e.code() == ErrorCode::NotFound => continue,
+                    Err(e) => return Err(Error::Git2(e)),
+                };
+
+                let parent_set = paint
+                    .entry(parent_oid)
+                    .or_insert_with(|| TipSet::new(tips.len()));
+                let was_full = *parent_set == full;
+                if !parent_set.union_with(&set) {
+                    continue;
+                }
+                let is_full = *parent_set == full;
+
+                if queued.contains(&parent_oid) {
+                    if !was_full && is_full {
+                        pending -= 1;
+                    }
+                } else {
+                    // Either never visited or visited too early (clock skew), walk it (again)
+                    if !is_full {
+                        pending += 1;
+                    }
+                    queue.push((parent_commit.time().seconds(), parent_oid));
+                    queued.insert(parent_oid);
+                }
+            }
+        }
+
+        let reachable_from = tips.iter().map(|oid| paint[oid].clone()).collect();
+
+        Ok(TipAncestry {
+            index,
+            reachable_from,
+        })
+    }
+
+    // Returns true if ancestor is reachable from descendant, including when both are the same commit.
+    pub(crate) fn is_ancestor(&self, ancestor: Oid, descendant: Oid) -> bool {
+        match (self.index.get(&ancestor), self.index.get(&descendant)) {
+            (Some(&ancestor), Some(&descendant)) => {
+                self.reachable_from[ancestor].contains(descendant)
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{Error, MainlineBranches};
+use git2::Repository;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// Name of the per-repository config file, looked up at the root of the working directory.
+pub const REPO_CONFIG_FILE_NAME: &str = ".git-stacked.toml";
+
+// Settings read from the config files.
+//
+// The user config ($XDG_CONFIG_HOME/git-stacked/config.toml) is read first, then the repository
+// config overrides whatever it sets.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // Replaces the built-in mainline branch names
+    pub mainline_branches: Option<Vec<String>>,
+    // Added to the mainline branch names, whether they're built-in or configured
+    pub extra_mainline_branches: Vec<String>,
+    // Enables or disables colored output
+    pub color: Option<bool>,
+    // Replaces "(detached)" before the roots of detached stacks
+    pub detached_label: Option<String>,
+    // Color of the detached label
+    pub detached_color: Option<HighlightColor>,
+    // Color of the mainline branch names
+    pub mainline_color: Option<HighlightColor>,
+    // Branches never marked detached, added to the ones given with --protect
+    pub protected_branches: Vec<String>,
+    // Color of the protected branch names
+    pub protected_color: Option<HighlightColor>,
+    // Background of the terminal the colors are chosen for
+    pub theme: Option<Theme>,
+}
+
+// Background of the terminal, dark terminals get the bright variant of the colors and light ones
+// the normal, darker, variant.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl Theme {
+    // Returns the color of the mainline branches unless configured otherwise.
+    pub fn default_mainline_color(self) -> HighlightColor {
+        match self {
+            Theme::Dark => HighlightColor::Green,
+            Theme::Light => HighlightColor::Blue,
+        }
+    }
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dark" => Ok(Theme::Dark),
+            "light" => Ok(Theme::Light),
+            _ => Err("expected dark or light".to_string()),
+        }
+    }
+}
+
+// Colors available to highlight parts of the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HighlightColor {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+}
+
+impl HighlightColor {
+    // Returns the ANSI escape sequence starting text in this color, in the variant suited to
+    // theme.
+    pub fn ansi_start(self, theme: Theme) -> &'static str {
+        match (theme, self) {
+            (Theme::Dark, HighlightColor::Red) => "\x1B[91m",
+            (Theme::Dark, HighlightColor::Green) => "\x1B[92m",
+            (Theme::Dark, HighlightColor::Yellow) => "\x1B[93m",
+            (Theme::Dark, HighlightColor::Blue) => "\x1B[94m",
+            (Theme::Dark, HighlightColor::Magenta) => "\x1B[95m",
+            (Theme::Dark, HighlightColor::Cyan) => "\x1B[96m",
+            (Theme::Light, HighlightColor::Red) => "\x1B[31m",
+            (Theme::Light, HighlightColor::Green) => "\x1B[32m",
+            (Theme::Light, HighlightColor::Yellow) => "\x1B[33m",
+            (Theme::Light, HighlightColor::Blue) => "\x1B[34m",
+            (Theme::Light, HighlightColor::Magenta) => "\x1B[35m",
+            (Theme::Light, HighlightColor::Cyan) => "\x1B[36m",
+        }
+    }
+
+    // Returns the CSS name of this color.
+    pub fn css_name(self) -> &'static str {
+        match self {
+            HighlightColor::Red => "red",
+            HighlightColor::Green => "green",
+            HighlightColor::Yellow => "goldenrod",
+            HighlightColor::Blue => "blue",
+            HighlightColor::Magenta => "magenta",
+            HighlightColor::Cyan => "darkcyan",
+        }
+    }
+}
+
+impl FromStr for HighlightColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "red" => Ok(HighlightColor::Red),
+            "green" => Ok(HighlightColor::Green),
+            "yellow" => Ok(HighlightColor::Yellow),
+            "blue" => Ok(HighlightColor::Blue),
+            "magenta" => Ok(HighlightColor::Magenta),
+            "cyan" => Ok(HighlightColor::Cyan),
+            _ => Err("expected red, green, yellow, blue, magenta or cyan".to_string()),
+        }
+    }
+}
+
+impl Config {
+    // Reads the config file at path, returns None if it doesn't exist.
+    pub fn load(path: &Path) -> Result<Option<Config>, Error> {
+        let data = match std::fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Error::Io(e)),
+        };
+
+        toml::from_str(&data)
+            .map(Some)
+            .map_err(|e| Error::InvalidConfig(path.display().to_string(), e))
+    }
+
+    // Reads the user config then the config of the repository, if any.
+    pub fn load_for_repo(repo: &Repository) -> Result<Config, Error> {
+        let mut config = Config::default();
+
+        if let Some(path) = user_config_path()
+            && let Some(user_config) = Config::load(&path)?
+        {
+            config = config.merge(user_config);
+        }
+
+        if let Some(workdir) = repo.workdir()
+            && let Some(repo_config) = Config::load(&workdir.join(REPO_CONFIG_FILE_NAME))?
+        {
+            config = config.merge(repo_config);
+        }
+
+        Ok(config)
+    }
+
+    // Returns self overridden by every setting present in other.
+    pub fn merge(mut self, other: Config) -> Config {
+        if other.mainline_branches.is_some() {
+            self.mainline_branches = other.mainline_branches;
+        }
+        self.extra_mainline_branches
+            .extend(other.extra_mainline_branches);
+        if other.color.is_some() {
+            self.color = other.color;
+        }
+        if other.detached_label.is_some() {
+            self.detached_label = other.detached_label;
+        }
+        if other.detached_color.is_some() {
+            self.detached_color = other.detached_color;
+        }
+        if other.mainline_color.is_some() {
+            self.mainline_color = other.mainline_color;
+        }
+        self.protected_branches.extend(other.protected_branches);
+        if other.protected_color.is_some() {
+            self.protected_color = other.protected_color;
+        }
+        if other.theme.is_some() {
+            self.theme = other.theme;
+        }
+        self
+    }
+
+    // Returns the mainline branch names resulting from this config.
+    pub fn mainline_branches(&self) -> MainlineBranches {
+        self.mainline_branches_with_default(None)
+    }
+
+    // Same as mainline_branches, with default_branch replacing the built-in mainline branch names
+    // unless the config sets them.
+    pub fn mainline_branches_with_default(&self, default_branch: Option<&str>) -> MainlineBranches {
+        let mut mainline = match (&self.mainline_branches, default_branch) {
+            (Some(names), _) => MainlineBranches(names.clone()),
+            (None, Some(default_branch)) => MainlineBranches(vec![default_branch.to_string()]),
+            (None, None) => MainlineBranches::default(),
+        };
+        for name in &self.extra_mainline_branches {
+            if !mainline.contains(name) {
+                mainline.0.push(name.clone());
+            }
+        }
+        mainline
+    }
+}
+
+// Returns the path of the user config file, $XDG_CONFIG_HOME/git-stacked/config.toml
+// or ~/.config/git-stacked/config.toml.
+fn user_config_path() -> Option<PathBuf> {
+    let config_home = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(config_home.join("git-stacked").join("config.toml"))
+}
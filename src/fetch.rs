@@ -0,0 +1,65 @@
+use crate::{BranchInfo, Error, is_remote_branch};
+use git2::{Cred, CredentialType, FetchOptions, RemoteCallbacks, Repository};
+use std::collections::BTreeSet;
+
+// Number of times credentials are asked for before giving up, libgit2 asks again as long as the
+// remote rejects them.
+const MAX_CREDENTIAL_ATTEMPTS: usize = 3;
+
+// Returns the names of the remotes tracked by the local branches, sorted by name.
+pub fn get_upstream_remotes(
+    repo: &Repository,
+    branches: &[BranchInfo],
+) -> Result<Vec<String>, Error> {
+    let mut remotes = BTreeSet::new();
+
+    for bi in branches.iter().filter(|bi| !is_remote_branch(&bi.name)) {
+        let refname = format!("refs/heads/{}", bi.name);
+        // Branches tracking another local branch have "." as their remote
+        if let Some(remote) = repo
+            .branch_upstream_remote(&refname)
+            .ok()
+            .and_then(|remote| remote.as_str().map(str::to_string))
+            .filter(|remote| remote != ".")
+        {
+            remotes.insert(remote);
+        }
+    }
+
+    Ok(remotes.into_iter().collect())
+}
+
+// Fetches the remote with its configured refspecs.
+//
+// Credentials come from the SSH agent for SSH remotes and from the git credential helpers for
+// HTTPS ones, like git itself does.
+pub fn fetch_remote(repo: &Repository, remote_name: &str) -> Result<(), Error> {
+    let config = repo.config()?;
+    let mut attempts = 0;
+
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(|url, username, allowed| {
+        attempts += 1;
+        if attempts > MAX_CREDENTIAL_ATTEMPTS {
+            return Err(git2::Error::from_str("no valid credentials found"));
+        }
+
+        if allowed.contains(CredentialType::SSH_KEY) {
+            Cred::ssh_key_from_agent(username.unwrap_or("git"))
+        } else if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            Cred::credential_helper(&config, url, username)
+        } else if allowed.contains(CredentialType::USERNAME) {
+            Cred::username(username.unwrap_or("git"))
+        } else {
+            Cred::default()
+        }
+    });
+
+    let mut fetch_options = FetchOptions::new();
+    fetch_options.remote_callbacks(callbacks);
+
+    let mut remote = repo.find_remote(remote_name)?;
+    remote
+        .fetch(&[] as &[&str], Some(&mut fetch_options), None)
+        .map_err(|e| Error::FetchFailed(remote_name.to_string(), e))
+}
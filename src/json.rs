@@ -0,0 +1,85 @@
+use crate::{AliasMap, BranchInfo, ChildrenMap};
+use git2::Oid;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+// Version of the shape of the JSON output, bumped whenever a field is removed, renamed or
+// changes meaning. Adding a field doesn't bump it.
+pub const JSON_FORMAT_VERSION: u32 = 1;
+
+// The document printed by --format json.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsonTree {
+    pub version: u32,
+    pub roots: Vec<JsonNode>,
+}
+
+// A branch of the JSON tree output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsonNode {
+    pub name: String,
+    // Other branches pointing at the same commit
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    pub oid: String,
+    pub detached: bool,
+    pub children: Vec<JsonNode>,
+}
+
+impl JsonTree {
+    // Builds the JSON tree of roots and all their descendants in children_map.
+    pub fn build(
+        branches: &[BranchInfo],
+        children_map: &ChildrenMap,
+        roots: &[String],
+        detached_roots: &HashSet<&str>,
+        aliases: &AliasMap,
+    ) -> JsonTree {
+        let oids: HashMap<&str, Oid> = branches
+            .iter()
+            .map(|bi| (bi.name.as_str(), bi.oid))
+            .collect();
+
+        JsonTree {
+            version: JSON_FORMAT_VERSION,
+            roots: roots
+                .iter()
+                .map(|root_branch_name| {
+                    let detached = detached_roots.contains(root_branch_name.as_str());
+                    build_json_node(root_branch_name, &oids, children_map, aliases, detached)
+                })
+                .collect(),
+        }
+    }
+}
+
+// Builds the JSON node for branch_name and all its descendants in children_map.
+fn build_json_node(
+    branch_name: &str,
+    oids: &HashMap<&str, Oid>,
+    children_map: &ChildrenMap,
+    aliases: &AliasMap,
+    detached: bool,
+) -> JsonNode {
+    let children = children_map
+        .0
+        .get(branch_name)
+        .map(|children_names| {
+            children_names
+                .iter()
+                .map(|child_name| build_json_node(child_name, oids, children_map, aliases, false))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    JsonNode {
+        name: branch_name.to_string(),
+        aliases: aliases.0.get(branch_name).cloned().unwrap_or_default(),
+        oid: oids
+            .get(branch_name)
+            .map(Oid::to_string)
+            .unwrap_or_default(),
+        detached,
+        children,
+    }
+}
@@ -0,0 +1,1717 @@
+mod ancestry;
+pub mod config;
+pub mod fetch;
+pub mod json;
+pub mod parent;
+pub mod prune;
+pub mod rename;
+pub mod restack;
+pub mod sync;
+
+use ancestry::TipAncestry;
+use git2::{BranchType, ErrorCode, Oid, Repository, Sort, StatusOptions, Tree};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::Instant;
+
+// What is printed to stderr besides errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    // Nothing
+    Quiet,
+    // Warnings
+    Normal,
+    // Warnings and progress
+    Verbose,
+}
+
+// Set by set_verbosity.
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+// Sets what warn and info print.
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+// Prints a warning to stderr, unless the verbosity is Quiet.
+pub fn warn(message: impl std::fmt::Display) {
+    if VERBOSITY.load(Ordering::Relaxed) >= Verbosity::Normal as u8 {
+        eprintln!("Warning: {}", message);
+    }
+}
+
+// Prints a progress message to stderr if the verbosity is Verbose.
+pub fn info(message: impl std::fmt::Display) {
+    if VERBOSITY.load(Ordering::Relaxed) >= Verbosity::Verbose as u8 {
+        eprintln!("git-stacked: {}", message);
+    }
+}
+
+// Branch names treated as mainline branches by default
+pub const MAINLINE_BRANCH_NAMES_ARRAY: [&str; 5] =
+    ["main", "master", "develop", "dev", "local-dev"];
+
+#[derive(Debug, onlyerror::Error)]
+pub enum Error {
+    #[error("git2 error: {0}")]
+    Git2(#[from] git2::Error),
+
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("invalid config file {0}: {1}")]
+    InvalidConfig(String, toml::de::Error),
+
+    #[error("invalid pattern in {0}: {1}")]
+    InvalidIgnoreFile(String, glob::PatternError),
+
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("not a git repository (or any parent directory)")]
+    NotARepository,
+
+    #[error("repository is bare")]
+    RepositoryIsBare,
+
+    #[error("branch not found: {0}")]
+    BranchNotFound(String),
+
+    #[error("branch cycle detected: {0:?}")]
+    CycleDetected(Vec<String>),
+
+    #[error("invalid branch name: {0}")]
+    InvalidBranchName(String),
+
+    #[error("branch already exists: {0}")]
+    BranchExists(String),
+
+    #[error("working tree has uncommitted changes")]
+    DirtyWorkingTree,
+
+    #[error("cannot restack {0} onto {1}: {1} is the branch itself or one of its descendants")]
+    InvalidRestack(String, String),
+
+    #[error("{0} and {1} have no common history")]
+    NoCommonHistory(String, String),
+
+    #[error("{0} branches need to be rebased onto the mainline")]
+    StaleBranches(usize),
+
+    #[error("{0} stacks are detached from the mainline")]
+    DetachedBranches(usize),
+
+    #[error(
+        "{0} branches exceed the limit of {1}, use --exclude or the ignore file to leave some out"
+    )]
+    TooManyBranches(usize, usize),
+
+    #[error("failed to fetch {0}: {1}")]
+    FetchFailed(String, git2::Error),
+
+    #[error(
+        "conflict while rebasing {0} onto {1}, rebase it with `git rebase --onto {1} {2} {0}` then run `git stacked sync --continue`"
+    )]
+    SyncConflict(String, String, String),
+
+    #[error("no sync in progress")]
+    NoSyncInProgress,
+
+    #[error("invalid sync state line: {0}")]
+    InvalidSyncState(String),
+
+    #[error("conflict while rebasing {0} at commit {1}, nothing was changed")]
+    RebaseConflict(String, String),
+}
+
+// Names of the branches considered mainline, in order of preference.
+#[derive(Debug, Clone)]
+pub struct MainlineBranches(pub Vec<String>);
+
+impl Default for MainlineBranches {
+    fn default() -> Self {
+        MainlineBranches(
+            MAINLINE_BRANCH_NAMES_ARRAY
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+        )
+    }
+}
+
+impl MainlineBranches {
+    pub fn contains(&self, branch_name: &str) -> bool {
+        self.0.iter().any(|name| name == branch_name)
+    }
+
+    // Returns the first mainline branch found among branches, in order of preference.
+    pub fn find<'a>(&self, branches: &'a [BranchInfo]) -> Option<&'a BranchInfo> {
+        self.0
+            .iter()
+            .find_map(|name| branches.iter().find(|bi| bi.name == *name))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BranchInfo {
+    pub name: String,
+    pub oid: Oid,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParentOfMap(pub HashMap<String, String>);
+
+// Represents the parent-child relationships between branches.
+// The key is the parent branch name, the values is a vector of child branch names.
+#[derive(Debug, Clone)]
+pub struct ChildrenMap(pub BTreeMap<String, Vec<String>>); // BTreeMap for sorted keys
+
+// Number of commits a branch is ahead/behind of its parent, keyed by branch name.
+#[derive(Debug, Clone)]
+pub struct AheadBehindMap(pub HashMap<String, (usize, usize)>);
+
+// Summary and time of the tip commit of a branch.
+#[derive(Debug, Clone)]
+pub struct CommitLog {
+    pub summary: String,
+    // Seconds since the Unix epoch
+    pub time: i64,
+}
+
+// Tip commit information, keyed by branch name.
+#[derive(Debug, Clone)]
+pub struct CommitLogMap(pub HashMap<String, CommitLog>);
+
+// Finds the repository containing path, looking into the parent directories like git does.
+pub fn open_repository(path: &Path) -> Result<Repository, Error> {
+    let repo = match Repository::discover(path) {
+        Ok(repo) => repo,
+        Err(e) if e.code() == ErrorCode::NotFound => return Err(Error::NotARepository),
+        Err(e) => return Err(Error::Git2(e)),
+    };
+
+    if repo.workdir().is_none() {
+        return Err(Error::RepositoryIsBare);
+    }
+
+    Ok(repo)
+}
+
+// Name of the file listing the branches to hide, looked up at the root of the working directory.
+pub const IGNORE_FILE_NAME: &str = ".git-stacked-ignore";
+
+// Reads the glob patterns of the ignore file of the repository, one per line. Blank lines and
+// lines starting with # are skipped.
+pub fn get_ignore_patterns(repo: &Repository) -> Result<Vec<glob::Pattern>, Error> {
+    let Some(workdir) = repo.workdir() else {
+        return Ok(Vec::new());
+    };
+    let path = workdir.join(IGNORE_FILE_NAME);
+
+    let data = match std::fs::read_to_string(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(Error::Io(e)),
+    };
+
+    data.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            glob::Pattern::new(line)
+                .map_err(|e| Error::InvalidIgnoreFile(path.display().to_string(), e))
+        })
+        .collect()
+}
+
+// Options of get_branches_with_options.
+#[derive(Debug, Default, Clone)]
+pub struct BranchOptions {
+    // Include the branches whose name isn't valid UTF-8, with the invalid sequences replaced,
+    // instead of skipping them
+    pub lossy_names: bool,
+}
+
+// Retrieves all local branches in the repository and returns their names and OIDs.
+//
+// The branches matching the ignore file are included, it's up to the display to hide them.
+pub fn get_branches(repo: &Repository) -> Result<Vec<BranchInfo>, Error> {
+    get_branches_with_options(repo, &BranchOptions::default())
+}
+
+// Same as get_branches, with the handling of invalid branch names set by options.
+pub fn get_branches_with_options(
+    repo: &Repository,
+    options: &BranchOptions,
+) -> Result<Vec<BranchInfo>, Error> {
+    let mut branches: Vec<BranchInfo> = Vec::new();
+    let branch_iter = repo.branches(Some(BranchType::Local))?;
+
+    for branch_result in branch_iter {
+        let (branch, _) = branch_result?;
+
+        let name = match branch.name()? {
+            Some(name_ref) => Some(name_ref.to_string()),
+            None if options.lossy_names => {
+                Some(String::from_utf8_lossy(branch.name_bytes()?).into_owned())
+            }
+            None => None,
+        };
+
+        if let Some(name_ref) = name.as_deref() {
+            // A symbolic branch stands for the commit of the branch it points to
+            let target_oid = match branch.get().target() {
+                Some(target_oid) => Some(target_oid),
+                None => branch
+                    .get()
+                    .resolve()
+                    .ok()
+                    .and_then(|reference| reference.target()),
+            };
+
+            match target_oid {
+                Some(target_oid) => branches.push(BranchInfo {
+                    name: name_ref.to_string(),
+                    oid: target_oid,
+                }),
+                None => warn(format_args!(
+                    "Branch {} is a symbolic reference which could not be resolved, skipping it",
+                    name_ref
+                )),
+            }
+        } else if let Ok(name_bytes) = branch.name_bytes() {
+            warn(format_args!(
+                "Branch name could not be processed or is not valid UTF-8: {:?}",
+                String::from_utf8_lossy(name_bytes)
+            ));
+        }
+    }
+
+    // Sort branch names for deterministic processing
+    branches.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(branches)
+}
+
+// Returns the default branch of the origin remote, which refs/remotes/origin/HEAD points to,
+// or None if it isn't known.
+pub fn get_default_branch(repo: &Repository) -> Result<Option<String>, Error> {
+    let reference = match repo.find_reference("refs/remotes/origin/HEAD") {
+        Ok(reference) => reference,
+        Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+        Err(e) => return Err(Error::Git2(e)),
+    };
+
+    Ok(reference
+        .symbolic_target()
+        .and_then(|target| target.strip_prefix("refs/remotes/origin/"))
+        .map(str::to_string))
+}
+
+// Prefix of the names of remote-tracking branches returned by get_remote_branches.
+pub const REMOTE_BRANCH_PREFIX: &str = "remotes/";
+
+// Returns true if branch_name is the name of a remote-tracking branch.
+pub fn is_remote_branch(branch_name: &str) -> bool {
+    branch_name.starts_with(REMOTE_BRANCH_PREFIX)
+}
+
+// Retrieves all remote-tracking branches in the repository, named like remotes/origin/main.
+// Symbolic references such as origin/HEAD are skipped.
+pub fn get_remote_branches(repo: &Repository) -> Result<Vec<BranchInfo>, Error> {
+    let mut branches: Vec<BranchInfo> = Vec::new();
+
+    for branch_result in repo.branches(Some(BranchType::Remote))? {
+        let (branch, _) = branch_result?;
+
+        if let (Some(name_ref), Some(target_oid)) = (branch.name()?, branch.get().target()) {
+            branches.push(BranchInfo {
+                name: format!("{}{}", REMOTE_BRANCH_PREFIX, name_ref),
+                oid: target_oid,
+            });
+        }
+    }
+
+    branches.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(branches)
+}
+
+// Other branches pointing at the same commit as a branch, keyed by the name of the branch.
+#[derive(Debug, Clone, Default)]
+pub struct AliasMap(pub HashMap<String, Vec<String>>);
+
+impl AliasMap {
+    // Returns the branch standing for branch_name, which is branch_name itself unless it's an alias.
+    pub fn representative<'a>(&'a self, branch_name: &'a str) -> &'a str {
+        self.0
+            .iter()
+            .find(|(_, aliases)| aliases.iter().any(|alias| alias == branch_name))
+            .map_or(branch_name, |(representative, _)| representative.as_str())
+    }
+}
+
+// Groups the branches pointing at the same commit, so that they're shown as a single node
+// instead of unrelated siblings.
+//
+// Only one branch per commit is kept in branches: the first mainline branch of the group if there
+// is one, the first branch of the group otherwise. The others are returned as its aliases.
+pub fn group_aliases(branches: &mut Vec<BranchInfo>, mainline: &MainlineBranches) -> AliasMap {
+    let mut groups: HashMap<Oid, Vec<BranchInfo>> = HashMap::new();
+    for bi in branches.iter() {
+        groups.entry(bi.oid).or_default().push(bi.clone());
+    }
+
+    let mut aliases = AliasMap::default();
+    for group in groups.values().filter(|group| group.len() > 1) {
+        let representative = mainline.find(group).unwrap_or(&group[0]);
+        aliases.0.insert(
+            representative.name.clone(),
+            group
+                .iter()
+                .filter(|bi| bi.name != representative.name)
+                .map(|bi| bi.name.clone())
+                .collect(),
+        );
+    }
+
+    let alias_names: HashSet<&String> = aliases.0.values().flatten().collect();
+    branches.retain(|bi| !alias_names.contains(&bi.name));
+
+    aliases
+}
+
+// Returns the short name of the branch HEAD points to, or None if HEAD is detached or unborn.
+pub fn get_current_branch(repo: &Repository) -> Result<Option<String>, Error> {
+    match repo.head() {
+        Ok(head) if head.is_branch() => Ok(head.shorthand().map(str::to_string)),
+        Ok(_) => Ok(None),
+        Err(e) if e.code() == ErrorCode::UnbornBranch || e.code() == ErrorCode::NotFound => {
+            Ok(None)
+        }
+        Err(e) => Err(Error::Git2(e)),
+    }
+}
+
+// Returns true in a repository without any commit yet: HEAD is unborn and there are no local
+// branches.
+pub fn is_empty_repository(repo: &Repository) -> Result<bool, Error> {
+    match repo.head() {
+        Ok(_) => return Ok(false),
+        Err(e) if e.code() == ErrorCode::UnbornBranch => {}
+        Err(e) => return Err(Error::Git2(e)),
+    }
+
+    Ok(repo.branches(Some(BranchType::Local))?.next().is_none())
+}
+
+// Status of a branch relative to its remote-tracking branch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpstreamStatus {
+    NoUpstream,
+    Tracking {
+        remote: String,
+        ahead: usize,
+        behind: usize,
+    },
+}
+
+// Upstream status, keyed by branch name.
+#[derive(Debug, Clone)]
+pub struct UpstreamStatusMap(pub HashMap<String, UpstreamStatus>);
+
+// Computes how many commits each branch is ahead/behind of its upstream.
+// Branches without an upstream, or whose upstream is gone, get UpstreamStatus::NoUpstream.
+pub fn get_upstream_statuses(
+    repo: &Repository,
+    branches: &[BranchInfo],
+) -> Result<UpstreamStatusMap, Error> {
+    let mut statuses = UpstreamStatusMap(HashMap::new());
+
+    for bi in branches.iter().filter(|bi| !is_remote_branch(&bi.name)) {
+        let branch = repo.find_branch(&bi.name, BranchType::Local)?;
+
+        let upstream = match branch.upstream() {
+            Ok(upstream) => upstream,
+            Err(e) if e.code() == ErrorCode::NotFound => {
+                statuses
+                    .0
+                    .insert(bi.name.clone(), UpstreamStatus::NoUpstream);
+                continue;
+            }
+            Err(e) => return Err(Error::Git2(e)),
+        };
+        let Some(upstream_oid) = upstream.get().target() else {
+            statuses
+                .0
+                .insert(bi.name.clone(), UpstreamStatus::NoUpstream);
+            continue;
+        };
+
+        let remote = match branch.get().name() {
+            Some(refname) => repo
+                .branch_upstream_remote(refname)
+                .ok()
+                .and_then(|remote| remote.as_str().map(str::to_string)),
+            None => None,
+        }
+        .unwrap_or_else(|| ".".to_string());
+
+        let (ahead, behind) = repo.graph_ahead_behind(bi.oid, upstream_oid)?;
+        statuses.0.insert(
+            bi.name.clone(),
+            UpstreamStatus::Tracking {
+                remote,
+                ahead,
+                behind,
+            },
+        );
+    }
+
+    Ok(statuses)
+}
+
+// Returns true if the working tree or the index has changes, untracked files don't count.
+pub fn has_uncommitted_changes(repo: &Repository) -> Result<bool, Error> {
+    let mut status_options = StatusOptions::new();
+    status_options
+        .include_untracked(false)
+        .include_ignored(false);
+
+    Ok(!repo.statuses(Some(&mut status_options))?.is_empty())
+}
+
+// Branches checked out in other worktrees, mapped to the name of the worktree.
+#[derive(Debug, Clone)]
+pub struct WorktreeBranchMap(pub HashMap<String, String>);
+
+// Returns the branches checked out in every worktree of the repository except the current one,
+// whose branch is the one returned by get_current_branch.
+// The main worktree is named "main" when the current one is a linked worktree.
+pub fn get_worktree_branches(repo: &Repository) -> Result<WorktreeBranchMap, Error> {
+    let mut worktree_branches = WorktreeBranchMap(HashMap::new());
+    let current_workdir = repo.workdir().and_then(|path| path.canonicalize().ok());
+
+    if repo.is_worktree() {
+        let main_repo = Repository::open(repo.commondir())?;
+        if let Some(branch_name) = get_current_branch(&main_repo)? {
+            worktree_branches.0.insert(branch_name, "main".to_string());
+        }
+    }
+
+    for worktree_name in repo.worktrees()?.iter().flatten() {
+        let worktree = repo.find_worktree(worktree_name)?;
+        // Skip worktrees whose directory is gone, they're waiting to be pruned
+        if worktree.validate().is_err() {
+            continue;
+        }
+        if worktree.path().canonicalize().ok() == current_workdir {
+            continue;
+        }
+
+        let worktree_repo = Repository::open_from_worktree(&worktree)?;
+        if let Some(branch_name) = get_current_branch(&worktree_repo)? {
+            worktree_branches
+                .0
+                .insert(branch_name, worktree_name.to_string());
+        }
+    }
+
+    Ok(worktree_branches)
+}
+
+// Memoizes merge-base computations, so that each pair of commits is only computed once.
+//
+// Parent detection doesn't need it, it walks the history once for all branches, but everything
+// comparing branches pair by pair should go through it.
+pub struct MergeBaseCache<'r> {
+    repo: &'r Repository,
+    cache: HashMap<(Oid, Oid), Option<Oid>>,
+}
+
+impl<'r> MergeBaseCache<'r> {
+    pub fn new(repo: &'r Repository) -> Self {
+        MergeBaseCache {
+            repo,
+            cache: HashMap::new(),
+        }
+    }
+
+    // Returns the merge base of a and b, or None if they have no common history.
+    pub fn merge_base(&mut self, a: Oid, b: Oid) -> Result<Option<Oid>, Error> {
+        // The merge base is symmetric, (a, b) and (b, a) share the same entry
+        let key = if a <= b { (a, b) } else { (b, a) };
+        if let Some(base) = self.cache.get(&key) {
+            return Ok(*base);
+        }
+
+        let base = match self.repo.merge_base(a, b) {
+            Ok(base) => Some(base),
+            Err(e) if e.code() == ErrorCode::NotFound => None,
+            Err(e) => return Err(Error::Git2(e)),
+        };
+        self.cache.insert(key, base);
+
+        Ok(base)
+    }
+
+    // Returns the number of merge bases actually computed.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+}
+
+// The branches considered as the parent of a branch by get_parent_of_relationships.
+#[derive(Debug, Clone)]
+pub struct ParentCandidates {
+    pub name: String,
+    // Branches whose tip is an ancestor of the tip of the branch, which is also their merge base
+    pub candidates: Vec<BranchInfo>,
+    // The candidate chosen as the parent
+    pub parent: Option<String>,
+    // Candidates on a line unrelated to the chosen parent, which could have been picked as well
+    pub ambiguous_with: Vec<String>,
+}
+
+// A branch with several candidate parents on unrelated lines, e.g. a branch merging two others.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmbiguousParent {
+    pub name: String,
+    pub parent: String,
+    pub other: String,
+}
+
+// Settings of the parent detection.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParentDetectionOptions {
+    // Only follow the first parent of merge commits, so that a branch merged into another one
+    // isn't an ancestor of it
+    pub first_parent: bool,
+    // How to choose between candidate parents on unrelated lines of history
+    pub rule: ParentRule,
+}
+
+// Rule choosing the parent of a branch among candidates where neither is an ancestor of the other.
+// A candidate descending from another one is always preferred to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParentRule {
+    // Keep the first candidate in branch name order
+    #[default]
+    Nearest,
+    // Pick the candidate whose tip commit is the most recent
+    Latest,
+}
+
+impl FromStr for ParentRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(ParentRule::Nearest),
+            "latest" => Ok(ParentRule::Latest),
+            _ => Err("expected nearest or latest".to_string()),
+        }
+    }
+}
+
+// Determines the parent-child relationships between branches based on their OIDs.
+//
+// The parent of a branch is the most specific branch whose tip is an ancestor of its tip.
+pub fn get_parent_of_relationships(
+    repo: &Repository,
+    branches: &[BranchInfo],
+) -> Result<ParentOfMap, Error> {
+    let (parent_of, _) = get_parent_of_relationships_with_warnings(
+        repo,
+        branches,
+        &ParentDetectionOptions::default(),
+    )?;
+
+    Ok(parent_of)
+}
+
+// Same as get_parent_of_relationships, also returns the branches whose parent was picked among
+// unrelated candidates.
+//
+// Parents recorded in the git config take precedence over inferred ones, see
+// get_configured_parents.
+pub fn get_parent_of_relationships_with_warnings(
+    repo: &Repository,
+    branches: &[BranchInfo],
+    options: &ParentDetectionOptions,
+) -> Result<(ParentOfMap, Vec<AmbiguousParent>), Error> {
+    let mut parent_of = get_configured_parents(repo, branches)?;
+    let mut warnings = Vec::new();
+
+    for pc in get_parent_candidates(repo, branches, options)? {
+        if parent_of.0.contains_key(&pc.name) {
+            continue;
+        }
+        let Some(parent) = pc.parent else {
+            continue;
+        };
+
+        for other in pc.ambiguous_with {
+            warnings.push(AmbiguousParent {
+                name: pc.name.clone(),
+                parent: parent.clone(),
+                other,
+            });
+        }
+        parent_of.0.insert(pc.name, parent);
+    }
+
+    Ok((parent_of, warnings))
+}
+
+// Returns the length of abbreviated OIDs set by core.abbrev, if it's a number between 4 and 40.
+// Other values like "auto" are ignored.
+pub fn get_abbrev_length(repo: &Repository) -> Result<Option<usize>, Error> {
+    let config = repo.config()?.snapshot()?;
+    let abbrev = match config.get_str("core.abbrev") {
+        Ok(abbrev) => abbrev,
+        Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+        Err(e) => return Err(Error::Git2(e)),
+    };
+
+    Ok(abbrev
+        .parse()
+        .ok()
+        .filter(|length| (4..=40).contains(length)))
+}
+
+// Overrides the parents in parent_of with the assumed (child, parent) pairs, without recording
+// them anywhere. Fails if one of the branches isn't in branches or if the assumed parents
+// create a cycle.
+pub fn assume_parents(
+    parent_of: &mut ParentOfMap,
+    branches: &[BranchInfo],
+    assumed: &[(String, String)],
+) -> Result<(), Error> {
+    for (child, parent) in assumed {
+        for name in [child, parent] {
+            if !branches.iter().any(|bi| &bi.name == name) {
+                return Err(Error::BranchNotFound(name.clone()));
+            }
+        }
+        parent_of.0.insert(child.clone(), parent.clone());
+    }
+
+    // Only the cycles going through an assumed child are reported, the others are left to
+    // build_children_and_roots
+    for (child, _) in assumed {
+        let mut chain: Vec<&str> = vec![child];
+        let mut current = parent_of.0.get(child);
+        while let Some(name) = current {
+            if name == child {
+                let mut cycle: Vec<String> = chain.iter().map(|n| n.to_string()).collect();
+                let smallest = (0..cycle.len()).min_by_key(|i| &cycle[*i]).unwrap_or(0);
+                cycle.rotate_left(smallest);
+                return Err(Error::CycleDetected(cycle));
+            }
+            if chain.contains(&name.as_str()) {
+                break;
+            }
+            chain.push(name);
+            current = parent_of.0.get(name);
+        }
+    }
+
+    Ok(())
+}
+
+// Name of the git config variable, under the branch.<name> section, recording the parent of a
+// branch.
+pub const STACKED_PARENT_CONFIG_KEY: &str = "stacked-parent";
+
+// Returns the parents recorded with branch.<name>.stacked-parent for the given local branches.
+//
+// A recorded parent that isn't one of the branches is ignored, so that the inferred parent
+// is used instead.
+pub fn get_configured_parents(
+    repo: &Repository,
+    branches: &[BranchInfo],
+) -> Result<ParentOfMap, Error> {
+    let config = repo.config()?.snapshot()?;
+    let names: HashSet<&str> = branches.iter().map(|bi| bi.name.as_str()).collect();
+
+    let mut parent_of = ParentOfMap(HashMap::new());
+
+    for bi in branches {
+        if is_remote_branch(&bi.name) {
+            continue;
+        }
+
+        let key = format!("branch.{}.{}", bi.name, STACKED_PARENT_CONFIG_KEY);
+        let parent = match config.get_string(&key) {
+            Ok(parent) => parent,
+            Err(e) if e.code() == ErrorCode::NotFound => continue,
+            Err(e) => return Err(Error::Git2(e)),
+        };
+
+        if parent != bi.name && names.contains(parent.as_str()) {
+            parent_of.0.insert(bi.name.clone(), parent);
+        }
+    }
+
+    Ok(parent_of)
+}
+
+// Returns, for each branch, the candidate parents considered by get_parent_of_relationships
+// and the one it chose.
+pub fn get_parent_candidates(
+    repo: &Repository,
+    branches: &[BranchInfo],
+    options: &ParentDetectionOptions,
+) -> Result<Vec<ParentCandidates>, Error> {
+    let mut parent_candidates = Vec::with_capacity(branches.len());
+
+    info(format_args!(
+        "walking the history of {} branches",
+        branches.len()
+    ));
+    let start = Instant::now();
+    let ancestry =
+        TipAncestry::compute(repo, branches.iter().map(|bi| bi.oid), options.first_parent)?;
+    info(format_args!("history walked in {:?}", start.elapsed()));
+
+    // Tip commit times, only looked up by the latest rule
+    let mut tip_times: HashMap<Oid, i64> = HashMap::new();
+    let mut tip_time = |oid: Oid| -> Result<i64, Error> {
+        if let Some(time) = tip_times.get(&oid) {
+            return Ok(*time);
+        }
+        let time = repo.find_commit(oid)?.time().seconds();
+        tip_times.insert(oid, time);
+        Ok(time)
+    };
+
+    for child_branch_info in branches {
+        let child_name = &child_branch_info.name;
+        let child_oid = child_branch_info.oid;
+
+        let mut candidates = Vec::new();
+        let mut current_best_parent_name: Option<String> = None;
+        let mut current_best_parent_oid: Option<Oid> = None;
+
+        for potential_parent_info in branches {
+            let potential_parent_name = &potential_parent_info.name;
+            let potential_parent_oid = potential_parent_info.oid;
+
+            if child_name == potential_parent_name || potential_parent_oid == child_oid {
+                continue;
+            }
+
+            if !ancestry.is_ancestor(potential_parent_oid, child_oid) {
+                continue; // Not an ancestor
+            }
+
+            // potential_parent is an ancestor
+            candidates.push(potential_parent_info.clone());
+
+            if current_best_parent_name.is_none() {
+                current_best_parent_name = Some(potential_parent_name.clone());
+                current_best_parent_oid = Some(potential_parent_oid);
+            } else if let Some(cbp_oid) = current_best_parent_oid
+                && cbp_oid != potential_parent_oid
+                && ancestry.is_ancestor(cbp_oid, potential_parent_oid)
+            {
+                // cbp_oid is an ancestor of potential_parent_oid,
+                // meaning potential_parent is more specific/descendant.
+                current_best_parent_name = Some(potential_parent_name.clone());
+                current_best_parent_oid = Some(potential_parent_oid);
+            } else if let Some(cbp_oid) = current_best_parent_oid
+                && options.rule == ParentRule::Latest
+                && cbp_oid != potential_parent_oid
+                && !ancestry.is_ancestor(potential_parent_oid, cbp_oid)
+                && tip_time(potential_parent_oid)? > tip_time(cbp_oid)?
+            {
+                // Unrelated lines of history, the most recent one wins
+                current_best_parent_name = Some(potential_parent_name.clone());
+                current_best_parent_oid = Some(potential_parent_oid);
+            }
+        }
+        // Every candidate should be an ancestor of the chosen parent, or share its tip
+        let ambiguous_with = match current_best_parent_oid {
+            Some(cbp_oid) => candidates
+                .iter()
+                .filter(|candidate| {
+                    candidate.oid != cbp_oid && !ancestry.is_ancestor(candidate.oid, cbp_oid)
+                })
+                .map(|candidate| candidate.name.clone())
+                .collect(),
+            None => Vec::new(),
+        };
+
+        parent_candidates.push(ParentCandidates {
+            name: child_name.clone(),
+            candidates,
+            parent: current_best_parent_name,
+            ambiguous_with,
+        });
+    }
+
+    Ok(parent_candidates)
+}
+
+// Looks up the tip commit of each branch and returns its summary and time.
+// Branches sharing a tip only look it up once.
+pub fn get_commit_logs(repo: &Repository, branches: &[BranchInfo]) -> Result<CommitLogMap, Error> {
+    let mut logs = CommitLogMap(HashMap::new());
+    let mut logs_by_oid: HashMap<Oid, CommitLog> = HashMap::new();
+
+    for bi in branches {
+        let log = match logs_by_oid.get(&bi.oid) {
+            Some(log) => log.clone(),
+            None => {
+                let commit = repo.find_commit(bi.oid)?;
+                let log = CommitLog {
+                    summary: String::from_utf8_lossy(commit.summary_bytes().unwrap_or_default())
+                        .into_owned(),
+                    time: commit.time().seconds(),
+                };
+                logs_by_oid.insert(bi.oid, log.clone());
+                log
+            }
+        };
+        logs.0.insert(bi.name.clone(), log);
+    }
+
+    Ok(logs)
+}
+
+// Parses a date given either relative to now, as a number of units like git does ("2.weeks",
+// "3 days ago"), or as an ISO date ("2024-05-01", "2024-05-01T12:30:00"), in UTC.
+// Returns the time in seconds since the Unix epoch.
+pub fn parse_date(value: &str, now: i64) -> Option<i64> {
+    parse_relative_date(value, now).or_else(|| parse_iso_date(value))
+}
+
+fn parse_relative_date(value: &str, now: i64) -> Option<i64> {
+    let mut words = value.split(['.', ' ']).filter(|word| !word.is_empty());
+
+    let count: i64 = words.next()?.parse().ok()?;
+    if count < 0 {
+        return None;
+    }
+    let unit = words.next()?;
+    if words.next().is_some_and(|word| word != "ago") || words.next().is_some() {
+        return None;
+    }
+
+    let unit_seconds = match unit.strip_suffix('s').unwrap_or(unit) {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 60 * 60,
+        "day" => 24 * 60 * 60,
+        "week" => 7 * 24 * 60 * 60,
+        "month" => 30 * 24 * 60 * 60,
+        "year" => 365 * 24 * 60 * 60,
+        _ => return None,
+    };
+
+    now.checked_sub(count.checked_mul(unit_seconds)?)
+}
+
+fn parse_iso_date(value: &str) -> Option<i64> {
+    let (date, time) = match value.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+
+    let mut date_parts = date.splitn(3, '-').map(str::parse::<i64>);
+    let year = date_parts.next()?.ok()?;
+    let month = date_parts.next()?.ok()?;
+    let day = date_parts.next()?.ok()?;
+    // Years past 9999 aren't ISO dates, and would overflow the number of seconds
+    if !(0..=9999).contains(&year) || !(1..=12).contains(&month) {
+        return None;
+    }
+    let is_leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if is_leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if !(1..=days_in_month).contains(&day) {
+        return None;
+    }
+
+    let seconds_of_day = match time {
+        Some(time) => {
+            let mut time_parts = time.splitn(3, ':').map(str::parse::<i64>);
+            let hours = time_parts.next()?.ok()?;
+            let minutes = time_parts.next()?.ok()?;
+            let seconds = time_parts.next().unwrap_or(Ok(0)).ok()?;
+            if !(0..=23).contains(&hours)
+                || !(0..=59).contains(&minutes)
+                || !(0..=59).contains(&seconds)
+            {
+                return None;
+            }
+            hours * 60 * 60 + minutes * 60 + seconds
+        }
+        None => 0,
+    };
+
+    // Days since the epoch of the civil date, from Howard Hinnant's days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    Some(days * 24 * 60 * 60 + seconds_of_day)
+}
+
+// Removes the branches whose tip commit is older than since, except the mainline branches.
+// commit_logs holds the tip commits of the branches, see get_commit_logs.
+pub fn remove_inactive_branches(
+    branches: &mut Vec<BranchInfo>,
+    mainline: &MainlineBranches,
+    commit_logs: &CommitLogMap,
+    since: i64,
+) {
+    branches.retain(|bi| {
+        mainline.contains(&bi.name)
+            || commit_logs
+                .0
+                .get(&bi.name)
+                .is_none_or(|log| log.time >= since)
+    });
+}
+
+// Computes how many commits each branch is ahead/behind of its parent.
+// Roots are compared against the mainline branch, if there is one.
+pub fn get_ahead_behind_counts(
+    repo: &Repository,
+    branches: &[BranchInfo],
+    parent_of: &ParentOfMap,
+    mainline: &MainlineBranches,
+) -> Result<AheadBehindMap, Error> {
+    let oids: HashMap<&str, Oid> = branches
+        .iter()
+        .map(|bi| (bi.name.as_str(), bi.oid))
+        .collect();
+    let mainline = mainline.find(branches);
+
+    // Branches sharing the same tip and parent tip share the same counts, cache them
+    // so that the merge-base computation only happens once per pair.
+    let mut cache: HashMap<(Oid, Oid), (usize, usize)> = HashMap::new();
+    let mut ahead_behind = AheadBehindMap(HashMap::new());
+
+    for bi in branches {
+        let base_oid = match parent_of.0.get(&bi.name) {
+            Some(parent_name) => oids.get(parent_name.as_str()).copied(),
+            None => mainline
+                .filter(|mainline| mainline.name != bi.name)
+                .map(|mainline| mainline.oid),
+        };
+        let Some(base_oid) = base_oid else {
+            continue;
+        };
+
+        let counts = match cache.get(&(bi.oid, base_oid)) {
+            Some(counts) => *counts,
+            None => {
+                let counts = repo.graph_ahead_behind(bi.oid, base_oid)?;
+                cache.insert((bi.oid, base_oid), counts);
+                counts
+            }
+        };
+        ahead_behind.0.insert(bi.name.clone(), counts);
+    }
+
+    Ok(ahead_behind)
+}
+
+// Number of commits unique to each branch, keyed by branch name.
+#[derive(Debug, Clone)]
+pub struct CommitCountMap(pub HashMap<String, usize>);
+
+// Returns the commit the commits of a branch start after: the tip of its parent, or for roots
+// their merge base with the mainline branch. None if the branch has no common history with it.
+fn branch_base(
+    bi: &BranchInfo,
+    oids: &HashMap<&str, Oid>,
+    parent_of: &ParentOfMap,
+    mainline_branch: Option<&BranchInfo>,
+    merge_bases: &mut MergeBaseCache,
+) -> Result<Option<Oid>, Error> {
+    match parent_of.0.get(&bi.name) {
+        Some(parent_name) => Ok(oids.get(parent_name.as_str()).copied()),
+        None => match mainline_branch {
+            Some(mainline_branch) => merge_bases.merge_base(bi.oid, mainline_branch.oid),
+            None => Ok(None),
+        },
+    }
+}
+
+// Counts the commits of each branch which aren't reachable from its parent. For roots, the
+// commits since their merge base with the mainline branch are counted, or all their commits if
+// they have no common history. Mainline branches aren't counted.
+pub fn get_commit_counts(
+    repo: &Repository,
+    branches: &[BranchInfo],
+    parent_of: &ParentOfMap,
+    mainline: &MainlineBranches,
+) -> Result<CommitCountMap, Error> {
+    let oids: HashMap<&str, Oid> = branches
+        .iter()
+        .map(|bi| (bi.name.as_str(), bi.oid))
+        .collect();
+    let mainline_branch = mainline.find(branches);
+
+    let mut merge_bases = MergeBaseCache::new(repo);
+    // A single revwalk is reused for every branch, it only needs to be reset between them
+    let mut revwalk = repo.revwalk()?;
+    let mut commit_counts = CommitCountMap(HashMap::new());
+
+    for bi in branches {
+        if mainline.contains(&bi.name) {
+            continue;
+        }
+
+        revwalk.reset()?;
+        revwalk.push(bi.oid)?;
+        if let Some(base_oid) =
+            branch_base(bi, &oids, parent_of, mainline_branch, &mut merge_bases)?
+        {
+            revwalk.hide(base_oid)?;
+        }
+
+        let mut count = 0;
+        for oid in &mut revwalk {
+            oid?;
+            count += 1;
+        }
+        commit_counts.0.insert(bi.name.clone(), count);
+    }
+
+    Ok(commit_counts)
+}
+
+// A commit of a branch, not reachable from its parent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BranchCommit {
+    pub oid: Oid,
+    pub summary: String,
+}
+
+// The newest commits of a branch and how many it has in total.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BranchCommits {
+    pub commits: Vec<BranchCommit>,
+    pub total: usize,
+}
+
+// Commits of each branch, keyed by branch name.
+#[derive(Debug, Clone)]
+pub struct BranchCommitsMap(pub HashMap<String, BranchCommits>);
+
+// Lists up to limit commits of each branch, newest first, among the ones counted by
+// get_commit_counts. Mainline branches are left out.
+pub fn get_branch_commits(
+    repo: &Repository,
+    branches: &[BranchInfo],
+    parent_of: &ParentOfMap,
+    mainline: &MainlineBranches,
+    limit: usize,
+) -> Result<BranchCommitsMap, Error> {
+    let oids: HashMap<&str, Oid> = branches
+        .iter()
+        .map(|bi| (bi.name.as_str(), bi.oid))
+        .collect();
+    let mainline_branch = mainline.find(branches);
+
+    let mut merge_bases = MergeBaseCache::new(repo);
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    let mut branch_commits = BranchCommitsMap(HashMap::new());
+
+    for bi in branches {
+        if mainline.contains(&bi.name) {
+            continue;
+        }
+
+        revwalk.reset()?;
+        revwalk.push(bi.oid)?;
+        if let Some(base_oid) =
+            branch_base(bi, &oids, parent_of, mainline_branch, &mut merge_bases)?
+        {
+            revwalk.hide(base_oid)?;
+        }
+
+        let mut commits = Vec::new();
+        let mut total = 0;
+        for oid in &mut revwalk {
+            let oid = oid?;
+            if commits.len() < limit {
+                let commit = repo.find_commit(oid)?;
+                commits.push(BranchCommit {
+                    oid,
+                    summary: String::from_utf8_lossy(commit.summary_bytes().unwrap_or_default())
+                        .into_owned(),
+                });
+            }
+            total += 1;
+        }
+        branch_commits
+            .0
+            .insert(bi.name.clone(), BranchCommits { commits, total });
+    }
+
+    Ok(branch_commits)
+}
+
+// Size of the changes of a branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffStat {
+    pub files: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+// Size of the changes of each branch, keyed by branch name.
+#[derive(Debug, Clone)]
+pub struct DiffStatMap(pub HashMap<String, DiffStat>);
+
+// Computes the diff stat of each branch since its merge base with its parent, or with the
+// mainline branch for roots. Mainline branches, roots without common history with the mainline
+// and branches deeper than max_depth are left out.
+pub fn get_diff_stats(
+    repo: &Repository,
+    branches: &[BranchInfo],
+    parent_of: &ParentOfMap,
+    mainline: &MainlineBranches,
+    max_depth: Option<usize>,
+) -> Result<DiffStatMap, Error> {
+    let oids: HashMap<&str, Oid> = branches
+        .iter()
+        .map(|bi| (bi.name.as_str(), bi.oid))
+        .collect();
+    let mainline_branch = mainline.find(branches);
+
+    let mut merge_bases = MergeBaseCache::new(repo);
+    // Siblings share the tree of their merge base with their parent
+    let mut trees: HashMap<Oid, Tree> = HashMap::new();
+    let mut diff_stats = DiffStatMap(HashMap::new());
+
+    for bi in branches {
+        if mainline.contains(&bi.name)
+            || max_depth
+                .is_some_and(|max_depth| get_ancestors(&bi.name, parent_of).len() > max_depth)
+        {
+            continue;
+        }
+
+        let other_oid = match parent_of.0.get(&bi.name) {
+            Some(parent_name) => oids.get(parent_name.as_str()).copied(),
+            None => mainline_branch.map(|mainline_branch| mainline_branch.oid),
+        };
+        let Some(base_oid) = (match other_oid {
+            Some(other_oid) => merge_bases.merge_base(bi.oid, other_oid)?,
+            None => None,
+        }) else {
+            continue;
+        };
+
+        for oid in [base_oid, bi.oid] {
+            if let Entry::Vacant(entry) = trees.entry(oid) {
+                entry.insert(repo.find_commit(oid)?.tree()?);
+            }
+        }
+
+        let diff = repo.diff_tree_to_tree(Some(&trees[&base_oid]), Some(&trees[&bi.oid]), None)?;
+        let stats = diff.stats()?;
+        diff_stats.0.insert(
+            bi.name.clone(),
+            DiffStat {
+                files: stats.files_changed(),
+                insertions: stats.insertions(),
+                deletions: stats.deletions(),
+            },
+        );
+    }
+
+    Ok(diff_stats)
+}
+
+// Author of the tip commit of each branch, keyed by branch name.
+#[derive(Debug, Clone)]
+pub struct AuthorMap(pub HashMap<String, String>);
+
+// Looks up the author of the tip commit of each branch: their name, or their email if the name is
+// empty.
+pub fn get_tip_authors(repo: &Repository, branches: &[BranchInfo]) -> Result<AuthorMap, Error> {
+    let mut authors = AuthorMap(HashMap::new());
+
+    for bi in branches {
+        let commit = repo.find_commit(bi.oid)?;
+        let author = commit.author();
+        let name = String::from_utf8_lossy(author.name_bytes());
+        let name = if name.is_empty() {
+            String::from_utf8_lossy(author.email_bytes())
+        } else {
+            name
+        };
+        authors.0.insert(bi.name.clone(), name.into_owned());
+    }
+
+    Ok(authors)
+}
+
+// A branch forked from an older commit of the mainline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleBranch {
+    pub name: String,
+    // Number of commits the mainline moved since the branch forked from it
+    pub behind: usize,
+}
+
+// Returns the branches whose merge base with the mainline branch isn't its tip, i.e. which need
+// to be rebased. Branches without common history with the mainline are ignored.
+pub fn get_stale_branches(
+    repo: &Repository,
+    branches: &[BranchInfo],
+    mainline: &MainlineBranches,
+) -> Result<Vec<StaleBranch>, Error> {
+    let Some(mainline_branch) = mainline.find(branches) else {
+        return Ok(Vec::new());
+    };
+
+    let mut merge_bases = MergeBaseCache::new(repo);
+    let mut stale = Vec::new();
+
+    for bi in branches {
+        if mainline.contains(&bi.name) {
+            continue;
+        }
+
+        let Some(base_oid) = merge_bases.merge_base(bi.oid, mainline_branch.oid)? else {
+            continue;
+        };
+        if base_oid == mainline_branch.oid {
+            continue;
+        }
+
+        let (behind, _) = repo.graph_ahead_behind(mainline_branch.oid, base_oid)?;
+        stale.push(StaleBranch {
+            name: bi.name.clone(),
+            behind,
+        });
+    }
+
+    Ok(stale)
+}
+
+// Returns the groups of branches whose tip commits differ but have the same tree, which usually
+// means one of them is redundant. Branches pointing at the same commit aren't reported, and
+// groups as well as the branches in them are sorted by name.
+pub fn get_identical_trees(
+    repo: &Repository,
+    branches: &[BranchInfo],
+) -> Result<Vec<Vec<String>>, Error> {
+    let mut by_tree: HashMap<Oid, Vec<&BranchInfo>> = HashMap::new();
+    for bi in branches {
+        let tree_oid = repo.find_commit(bi.oid)?.tree_id();
+        by_tree.entry(tree_oid).or_default().push(bi);
+    }
+
+    let mut groups: Vec<Vec<String>> = by_tree
+        .into_values()
+        .filter(|group| group.iter().any(|bi| bi.oid != group[0].oid))
+        .map(|group| {
+            let mut names: Vec<String> = group.into_iter().map(|bi| bi.name.clone()).collect();
+            names.sort();
+            names
+        })
+        .collect();
+    groups.sort();
+
+    Ok(groups)
+}
+
+// Where sibling branches diverge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForkPoint {
+    // Merge base of all the siblings
+    pub oid: Oid,
+    // Number of commits of each sibling since the merge base, in children order
+    pub diverged: Vec<(String, usize)>,
+}
+
+// Fork point of the children of each branch having several, keyed by parent branch name.
+#[derive(Debug, Clone)]
+pub struct ForkPointMap(pub HashMap<String, ForkPoint>);
+
+// Computes the merge base of the children of each branch with at least two children, and how
+// many commits each of them has since. Siblings without common history are left out.
+pub fn get_fork_points(
+    repo: &Repository,
+    branches: &[BranchInfo],
+    children_map: &ChildrenMap,
+) -> Result<ForkPointMap, Error> {
+    let oids: HashMap<&str, Oid> = branches
+        .iter()
+        .map(|bi| (bi.name.as_str(), bi.oid))
+        .collect();
+
+    let mut merge_bases = MergeBaseCache::new(repo);
+    let mut fork_points = ForkPointMap(HashMap::new());
+
+    'parents: for (parent_name, children_names) in &children_map.0 {
+        let children: Vec<(&String, Oid)> = children_names
+            .iter()
+            .filter_map(|name| oids.get(name.as_str()).map(|oid| (name, *oid)))
+            .collect();
+        if children.len() < 2 {
+            continue;
+        }
+
+        let mut base_oid = children[0].1;
+        for (_, oid) in &children[1..] {
+            match merge_bases.merge_base(base_oid, *oid)? {
+                Some(oid) => base_oid = oid,
+                None => continue 'parents,
+            }
+        }
+
+        let mut diverged = Vec::with_capacity(children.len());
+        for (name, oid) in children {
+            let (ahead, _) = repo.graph_ahead_behind(oid, base_oid)?;
+            diverged.push((name.clone(), ahead));
+        }
+
+        fork_points.0.insert(
+            parent_name.clone(),
+            ForkPoint {
+                oid: base_oid,
+                diverged,
+            },
+        );
+    }
+
+    Ok(fork_points)
+}
+
+// Commit times of the fork point of each branch and of its tip, ordering siblings by when they
+// were stacked.
+#[derive(Debug, Clone)]
+pub struct TopoOrderMap(pub HashMap<String, (i64, i64)>);
+
+// Computes the sort key of each branch for the topological order: the time of its fork point
+// then the time of its tip. The fork point is the oldest commit of the branch in first-parent
+// order which isn't reachable from its base, the tip of its parent or for roots their merge base
+// with the mainline branch. Branches without commits of their own fork at their tip, branches
+// without common history with the mainline branch at their oldest commit.
+pub fn get_topo_order(
+    repo: &Repository,
+    branches: &[BranchInfo],
+    parent_of: &ParentOfMap,
+    mainline: &MainlineBranches,
+) -> Result<TopoOrderMap, Error> {
+    let oids: HashMap<&str, Oid> = branches
+        .iter()
+        .map(|bi| (bi.name.as_str(), bi.oid))
+        .collect();
+    let mainline_branch = mainline.find(branches);
+
+    let mut merge_bases = MergeBaseCache::new(repo);
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL)?;
+    let mut topo_order = TopoOrderMap(HashMap::new());
+
+    for bi in branches {
+        let tip_time = repo.find_commit(bi.oid)?.time().seconds();
+
+        revwalk.reset()?;
+        revwalk.simplify_first_parent()?;
+        revwalk.push(bi.oid)?;
+        if let Some(base_oid) =
+            branch_base(bi, &oids, parent_of, mainline_branch, &mut merge_bases)?
+        {
+            revwalk.hide(base_oid)?;
+        }
+
+        let mut fork_oid = None;
+        for oid in &mut revwalk {
+            fork_oid = Some(oid?);
+        }
+        let fork_time = match fork_oid {
+            Some(oid) => repo.find_commit(oid)?.time().seconds(),
+            None => tip_time,
+        };
+
+        topo_order.0.insert(bi.name.clone(), (fork_time, tip_time));
+    }
+
+    Ok(topo_order)
+}
+
+// Whether each branch is ready to merge, keyed by branch name.
+#[derive(Debug, Clone)]
+pub struct MergeReadinessMap(pub HashMap<String, bool>);
+
+// Returns, for each branch other than the mainline branches and target, whether it's ready to
+// merge into target: every branch of its parent chain down to the root must already be merged
+// into target, that is have its tip reachable from the tip of target.
+pub fn get_merge_readiness(
+    repo: &Repository,
+    branches: &[BranchInfo],
+    parent_of: &ParentOfMap,
+    mainline: &MainlineBranches,
+    target: &BranchInfo,
+) -> Result<MergeReadinessMap, Error> {
+    let oids: HashMap<&str, Oid> = branches
+        .iter()
+        .map(|bi| (bi.name.as_str(), bi.oid))
+        .collect();
+
+    // Each branch is checked once, even when it's part of several parent chains
+    let mut merged: HashMap<String, bool> = HashMap::new();
+    let mut readiness = MergeReadinessMap(HashMap::new());
+
+    for bi in branches {
+        if bi.name == target.name || mainline.contains(&bi.name) {
+            continue;
+        }
+
+        let mut ready = true;
+        for ancestor in get_ancestors(&bi.name, parent_of) {
+            let Some(&oid) = oids.get(ancestor.as_str()) else {
+                continue;
+            };
+            let is_merged = match merged.get(&ancestor) {
+                Some(&is_merged) => is_merged,
+                None => {
+                    let is_merged =
+                        oid == target.oid || repo.graph_descendant_of(target.oid, oid)?;
+                    merged.insert(ancestor, is_merged);
+                    is_merged
+                }
+            };
+            if !is_merged {
+                ready = false;
+                break;
+            }
+        }
+
+        readiness.0.insert(bi.name.clone(), ready);
+    }
+
+    Ok(readiness)
+}
+
+#[derive(Debug, Clone)]
+pub struct ChildrenAndRoots {
+    pub children_map: ChildrenMap,
+    pub roots: Vec<String>,
+}
+
+// Walks the parent chain of every branch and returns the branches forming the first cycle found,
+// in parent order starting from the branch with the smallest name.
+pub fn find_cycle(parent_of: &ParentOfMap) -> Option<Vec<String>> {
+    let mut children: Vec<&String> = parent_of.0.keys().collect();
+    children.sort();
+
+    // Branches whose parent chain is known to end at a root
+    let mut acyclic: HashSet<&str> = HashSet::new();
+
+    for child in children {
+        let mut chain: Vec<&str> = Vec::new();
+        let mut current = Some(child.as_str());
+
+        while let Some(name) = current {
+            if acyclic.contains(name) {
+                break;
+            }
+            if let Some(pos) = chain.iter().position(|n| *n == name) {
+                let mut cycle: Vec<String> = chain[pos..].iter().map(|n| n.to_string()).collect();
+                let smallest = (0..cycle.len()).min_by_key(|i| &cycle[*i]).unwrap_or(0);
+                cycle.rotate_left(smallest);
+                return Some(cycle);
+            }
+            chain.push(name);
+            current = parent_of.0.get(name).map(String::as_str);
+        }
+
+        acyclic.extend(chain);
+    }
+
+    None
+}
+
+// Removes the branches whose parents form a cycle from branches and parent_of, so that the rest
+// of the tree can be built. Their children become roots. Returns the cycles removed.
+pub fn remove_cycles(
+    branches: &mut Vec<BranchInfo>,
+    parent_of: &mut ParentOfMap,
+) -> Vec<Vec<String>> {
+    let mut cycles = Vec::new();
+    while let Some(cycle) = find_cycle(parent_of) {
+        parent_of
+            .0
+            .retain(|child, parent| !cycle.contains(child) && !cycle.contains(parent));
+        cycles.push(cycle);
+    }
+
+    branches.retain(|bi| !cycles.iter().any(|cycle| cycle.contains(&bi.name)));
+
+    cycles
+}
+
+impl ChildrenAndRoots {
+    // Removes every branch not in keep from the tree.
+    pub fn retain(&mut self, keep: &HashSet<String>) {
+        self.children_map
+            .0
+            .retain(|parent, _| keep.contains(parent));
+        for children in self.children_map.0.values_mut() {
+            children.retain(|child| keep.contains(child));
+        }
+        self.children_map
+            .0
+            .retain(|_, children| !children.is_empty());
+        self.roots.retain(|root| keep.contains(root));
+    }
+
+    // Reorders the roots and the children of every branch by key. The sort is stable, so
+    // branches with the same key stay sorted by name.
+    pub fn sort_by_key<K: Ord>(&mut self, key: impl Fn(&str) -> K) {
+        for children in self.children_map.0.values_mut() {
+            children.sort_by_key(|child| key(child));
+        }
+        self.roots.sort_by_key(|root| key(root));
+    }
+}
+
+// Returns the ancestors of branch_name, from its parent up to its root.
+pub fn get_ancestors(branch_name: &str, parent_of: &ParentOfMap) -> Vec<String> {
+    let mut ancestors = Vec::new();
+    let mut current = parent_of.0.get(branch_name);
+    while let Some(parent_name) = current {
+        ancestors.push(parent_name.clone());
+        current = parent_of.0.get(parent_name);
+    }
+    ancestors
+}
+
+// Returns all the descendants of branch_name, depth first.
+pub fn get_descendants(branch_name: &str, children_map: &ChildrenMap) -> Vec<String> {
+    let mut descendants = Vec::new();
+    if let Some(children_names) = children_map.0.get(branch_name) {
+        for child_name in children_names {
+            descendants.push(child_name.clone());
+            descendants.extend(get_descendants(child_name, children_map));
+        }
+    }
+    descendants
+}
+
+// Builds the children_map and identifies root branches based on parent-child relationships.
+// Fails if the relationships contain a cycle, since such branches would never be printed.
+pub fn build_children_and_roots(
+    branches: &[BranchInfo],
+    parent_of: &ParentOfMap,
+) -> Result<ChildrenAndRoots, Error> {
+    if let Some(cycle) = find_cycle(parent_of) {
+        return Err(Error::CycleDetected(cycle));
+    }
+
+    let mut children_map = ChildrenMap(BTreeMap::new());
+
+    let mut all_branch_names: HashSet<String> = HashSet::new();
+    for bi in branches {
+        all_branch_names.insert(bi.name.clone());
+    }
+
+    let mut children_with_parents: HashSet<String> = HashSet::new();
+    for (child, parent) in &parent_of.0 {
+        children_map
+            .0
+            .entry(parent.clone())
+            .or_default()
+            .push(child.clone());
+        children_with_parents.insert(child.clone());
+    }
+
+    // Sort children within each parent's list for deterministic output
+    for children in children_map.0.values_mut() {
+        children.sort();
+    }
+
+    let mut roots: Vec<String> = all_branch_names
+        .difference(&children_with_parents)
+        .cloned()
+        .collect();
+    roots.sort(); // Sort roots for deterministic output
+
+    Ok(ChildrenAndRoots {
+        children_map,
+        roots,
+    })
+}
+
+// Removes the parent of the mainline branches, so that the trunk is always at the top of
+// its stack, even when it was branched off another branch.
+pub fn detach_mainline(parent_of: &mut ParentOfMap, mainline: &MainlineBranches) {
+    parent_of.0.retain(|child, _| !mainline.contains(child));
+}
+
+// Returns the roots which are neither mainline nor protected branches and not stacked on another
+// branch, which is the case of subtree roots selected with --root.
+pub fn detached_roots<'b>(
+    roots: &'b [String],
+    parent_of: &ParentOfMap,
+    mainline: &MainlineBranches,
+    protected: &HashSet<String>,
+) -> HashSet<&'b str> {
+    roots
+        .iter()
+        .map(String::as_str)
+        .filter(|root| {
+            !mainline.contains(root)
+                && !protected.contains(*root)
+                && !parent_of.0.contains_key(*root)
+        })
+        .collect()
+}
+
+// Stacked branch tree returned by build_stack.
+#[derive(Debug, Clone)]
+pub struct Stack {
+    pub children_map: ChildrenMap,
+    pub roots: Vec<String>,
+    // Branches pointing at the same commit as a branch of the tree, which aren't in the tree
+    pub aliases: AliasMap,
+}
+
+// Computes the stacked branch tree of the repository: the children of each branch and the root branches.
+//
+// The branches sharing a commit are grouped as with group_aliases, using the default mainline branches.
+pub fn build_stack(repo: &Repository) -> Result<Stack, Error> {
+    let mut branches = get_branches(repo)?;
+    let aliases = group_aliases(&mut branches, &MainlineBranches::default());
+    let parent_of = get_parent_of_relationships(repo, &branches)?;
+
+    let ChildrenAndRoots {
+        children_map,
+        roots,
+    } = build_children_and_roots(&branches, &parent_of)?;
+
+    Ok(Stack {
+        children_map,
+        roots,
+        aliases,
+    })
+}
@@ -1,277 +1,1522 @@
-use git2::{BranchType, ErrorCode, Oid, Repository};
-use std::collections::{BTreeMap, HashMap, HashSet};
+mod render;
 
-// Constants for coloring and mainline branches
-const MAINLINE_BRANCH_NAMES_ARRAY: [&str; 5] = ["main", "master", "develop", "dev", "local-dev"];
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use git_stacked::config::{Config, HighlightColor, Theme};
+use git_stacked::fetch::{fetch_remote, get_upstream_remotes};
+use git_stacked::parent::set_parent;
+use git_stacked::prune::{delete_branches, get_merged_branches};
+use git_stacked::rename::rename_branch;
+use git_stacked::restack::{RestackedBranch, restack};
+use git_stacked::sync::{abort_sync, continue_sync, plan_sync, sync};
+use git_stacked::{
+    BranchInfo, BranchOptions, ChildrenAndRoots, ChildrenMap, Error, MainlineBranches,
+    ParentDetectionOptions, ParentOfMap, ParentRule, Verbosity, assume_parents,
+    build_children_and_roots, detach_mainline, detached_roots, find_cycle, get_abbrev_length,
+    get_ahead_behind_counts, get_ancestors, get_branch_commits, get_branches,
+    get_branches_with_options, get_commit_counts, get_commit_logs, get_configured_parents,
+    get_current_branch, get_default_branch, get_descendants, get_diff_stats, get_fork_points,
+    get_identical_trees, get_ignore_patterns, get_merge_readiness, get_parent_candidates,
+    get_parent_of_relationships_with_warnings, get_remote_branches, get_stale_branches,
+    get_tip_authors, get_topo_order, get_upstream_statuses, get_worktree_branches, group_aliases,
+    has_uncommitted_changes, info, is_empty_repository, open_repository, parse_date, remove_cycles,
+    remove_inactive_branches, set_verbosity, warn,
+};
+use git2::Repository;
+use glob::Pattern;
+use render::{
+    ASCII_GLYPHS, Glyphs, RenderOptions, UNICODE_GLYPHS, print_counts, print_dot, print_head_path,
+    print_html, print_json, print_jsonl, print_list, print_mermaid, print_porcelain, print_stats,
+    print_svg, print_tree, print_tsv,
+};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-const RED_START: &str = "\x1B[91m"; // Bright Red
-const COLOR_RESET: &str = "\x1B[0m";
-const DETACHED_PREFIX_TEXT: &str = "(detached)";
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// ASCII tree
+    Ascii,
+    /// Nested JSON document
+    Json,
+    /// Graphviz DOT digraph
+    Dot,
+    /// One JSON object per branch with its parent
+    Jsonl,
+    /// Mermaid flowchart
+    Mermaid,
+    /// Branch names indented by depth, without connectors
+    List,
+    /// Standalone SVG image
+    Svg,
+    /// Tab-separated values with a header line
+    Tsv,
+    /// Standalone HTML page with a collapsible tree
+    Html,
+    /// Stable tab-separated lines for scripts, selected with --porcelain
+    #[value(skip)]
+    Porcelain,
+}
+
+impl Format {
+    // Renders the branch tree to stdout using the backend matching this format.
+    fn render(
+        self,
+        branches: &[BranchInfo],
+        parent_of: &ParentOfMap,
+        children_map: &ChildrenMap,
+        roots: &[String],
+        options: &RenderOptions,
+    ) -> Result<(), Error> {
+        match self {
+            Format::Ascii => print_tree(
+                &mut std::io::stdout().lock(),
+                branches,
+                parent_of,
+                children_map,
+                roots,
+                options,
+            ),
+            Format::Json => print_json(branches, parent_of, children_map, roots, options),
+            Format::Dot => print_dot(branches, parent_of, children_map, roots, options),
+            Format::Jsonl => print_jsonl(branches, parent_of, options),
+            Format::Mermaid => print_mermaid(branches, parent_of, children_map, roots, options),
+            Format::Tsv => print_tsv(branches, parent_of, children_map, roots, options),
+            Format::Svg => print_svg(parent_of, children_map, roots, options),
+            Format::Html => print_html(parent_of, children_map, roots, options),
+            Format::Porcelain => print_porcelain(parent_of, children_map, roots, options),
+            Format::List => print_list(&mut std::io::stdout().lock(), children_map, roots, options),
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Rebase a branch onto a new parent
+    Restack {
+        /// Branch to rebase
+        branch: String,
+
+        /// New parent of the branch
+        #[arg(long, value_name = "NEW_PARENT")]
+        onto: String,
+
+        /// Also rebase the descendants of the branch onto their rebased parents
+        #[arg(long)]
+        descendants: bool,
+    },
+    /// Rebase every stack forked from an older commit of the mainline onto its tip
+    Sync {
+        /// Only print the planned rebases
+        #[arg(long, conflicts_with_all = ["continue_", "abort"])]
+        dry_run: bool,
+
+        /// Resume a sync stopped on a conflict, once the conflicting branch is rebased
+        #[arg(long = "continue", conflicts_with = "abort")]
+        continue_: bool,
+
+        /// Forget about a sync stopped on a conflict
+        #[arg(long)]
+        abort: bool,
+    },
+    /// Record the parent of a branch and print the updated tree
+    SetParent {
+        /// Branch to record the parent of
+        branch: String,
 
-#[derive(Debug, onlyerror::Error)]
-enum Error {
-    #[error("git2 error: {0}")]
-    Git2(#[from] git2::Error),
+        /// Parent of the branch
+        parent: String,
+    },
+    /// Rename a branch and print the updated tree
+    Rename {
+        /// Current name of the branch
+        old: String,
 
-    #[error("repository is bare")]
-    RepositoryIsBare,
+        /// New name of the branch
+        new: String,
+    },
+    /// Report how the branches are detected and the problems found
+    Doctor,
+    /// Print the branch names one per line, for shell completions
+    #[command(hide = true)]
+    ListBranches,
+    /// Delete the branches fully merged into a mainline branch
+    Prune {
+        /// Only print the branches that would be deleted, this is the default without --force
+        #[arg(long, conflicts_with = "force")]
+        dry_run: bool,
+
+        /// Actually delete the branches
+        #[arg(long)]
+        force: bool,
+    },
 }
 
-#[derive(Debug, Clone)]
-struct BranchInfo {
-    name: String,
-    oid: Oid,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum GlyphSet {
+    /// Unicode box-drawing characters
+    Unicode,
+    /// Pure ASCII characters
+    Ascii,
 }
 
-struct ParentOfMap(HashMap<String, String>);
+impl GlyphSet {
+    fn glyphs(self) -> &'static Glyphs {
+        match self {
+            GlyphSet::Unicode => &UNICODE_GLYPHS,
+            GlyphSet::Ascii => &ASCII_GLYPHS,
+        }
+    }
+}
 
-// Represents the parent-child relationships between branches.
-// The key is the parent branch name, the values is a vector of child branch names.
-struct ChildrenMap(BTreeMap<String, Vec<String>>); // BTreeMap for sorted keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ColorChoice {
+    Always,
+    Auto,
+    Never,
+}
 
-// Prints the ASCII tree structure in children_map recursively.
-fn print_ascii_tree_recursive(
-    parent_branch_name: &str,
-    children_map: &ChildrenMap,
-    current_prefix: &str,
-) {
-    if let Some(children_names) = children_map.0.get(parent_branch_name) {
-        let num_children = children_names.len();
-        for (i, child_name) in children_names.iter().enumerate() {
-            let is_last_child = i == num_children - 1;
-            let connector = if is_last_child {
-                "└── "
-            } else {
-                "├── "
-            };
-            println!("{}{}{}", current_prefix, connector, child_name);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum GroupBy {
+    /// Author of the tip commit of the root of each stack
+    Author,
+}
 
-            let prefix_for_grandchildren = format!(
-                "{}{}",
-                current_prefix,
-                if is_last_child { "    " } else { "│   " }
-            );
-            print_ascii_tree_recursive(child_name, children_map, &prefix_for_grandchildren);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SortOrder {
+    /// Alphabetically
+    Name,
+    /// Most recent tip commit first
+    Date,
+    /// Most commits ahead of the parent first
+    Ahead,
+    /// In stacking order, the branch whose first commit is the oldest first
+    Topo,
+}
+
+// Marker before the roots of detached stacks, unless configured otherwise
+const DEFAULT_DETACHED_LABEL: &str = "(detached)";
+
+// Number of hexadecimal digits of the abbreviated OIDs when core.abbrev isn't set
+const DEFAULT_ABBREV_LENGTH: usize = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum FailCondition {
+    /// A stack has a root which isn't a mainline branch
+    Detached,
+    /// A branch forked from an older commit of the mainline
+    Stale,
+    /// The parents of some branches form a cycle, the tree is printed without them
+    Cycle,
+}
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "git-stacked",
+    bin_name = "git stacked",
+    version,
+    about = "Visualize stacked git branches"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path of the repository, or of any directory inside it. Can be repeated to print the tree of
+    /// each repository under a header
+    #[arg(long, value_name = "PATH", default_value = ".")]
+    repo: Vec<PathBuf>,
+
+    /// Run as if started in PATH, like git -C. When repeated, each path is relative to the
+    /// previous one
+    #[arg(short = 'C', value_name = "PATH")]
+    change_dir: Vec<PathBuf>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Ascii)]
+    format: Format,
+
+    /// Print one line per branch in a format stable across versions: name, parent, depth and
+    /// flags separated by tabs
+    #[arg(long, conflicts_with = "format")]
+    porcelain: bool,
+
+    /// Group the stacks of the ascii tree under a header
+    #[arg(long, value_enum, value_name = "KEY")]
+    group_by: Option<GroupBy>,
+
+    /// Order of the roots and of the children of each branch
+    #[arg(long, value_enum, default_value_t = SortOrder::Name)]
+    sort: SortOrder,
+
+    /// Treat this branch as the mainline instead of the built-in mainline names
+    #[arg(long, value_name = "BRANCH")]
+    base: Option<String>,
+
+    /// Only show the path from the root down to this branch, and its descendants
+    #[arg(long, value_name = "BRANCH")]
+    only: Option<String>,
+
+    /// Don't print branches deeper than this, roots are at depth 0
+    #[arg(long, value_name = "N")]
+    depth: Option<usize>,
+
+    /// Show the summary and date of the last commit of each branch
+    #[arg(long)]
+    log: bool,
+
+    /// Maximum number of characters of the commit summaries shown with --log
+    #[arg(long, value_name = "N", default_value_t = 50)]
+    log_width: usize,
+
+    /// Show how many commits each branch is ahead/behind of its upstream
+    #[arg(long)]
+    remote: bool,
+
+    /// Fetch the remotes tracked by the local branches first. Nothing else accesses the network
+    #[arg(long)]
+    fetch: bool,
+
+    /// When to use colors: auto uses them when stdout is a terminal and NO_COLOR isn't set
+    #[arg(long, value_enum, value_name = "WHEN")]
+    color: Option<ColorChoice>,
+
+    /// Disable colored output, same as --color=never
+    #[arg(long, conflicts_with = "color")]
+    no_color: bool,
+
+    /// Only show the branches with a tip commit newer than DATE, e.g. "2.weeks" or "2024-05-01"
+    #[arg(long, value_name = "DATE", value_parser = parse_since)]
+    since: Option<i64>,
+
+    /// Remove this prefix from the displayed branch names, e.g. "username/feature/"
+    #[arg(long, value_name = "PREFIX")]
+    strip_prefix: Option<String>,
+
+    /// Print a summary of the tree to stderr: branches, stacks, max depth and detached roots
+    #[arg(long)]
+    stats: bool,
+
+    /// Only print the number of branches, of stacks and the max depth on one line, as JSON with
+    /// --format json
+    #[arg(long)]
+    count_only: bool,
+
+    /// Marker before the roots of detached stacks, defaults to "(detached)"
+    #[arg(long, value_name = "TEXT")]
+    detached_label: Option<String>,
+
+    /// Color of the detached marker: red (default), green, yellow, blue, magenta or cyan
+    #[arg(long, value_name = "COLOR")]
+    detached_color: Option<HighlightColor>,
+
+    /// Color of the mainline branches: red, green (default), yellow, blue (default with the
+    /// light theme), magenta or cyan
+    #[arg(long, value_name = "COLOR")]
+    mainline_color: Option<HighlightColor>,
+
+    /// Background of the terminal: dark (default) for bright colors, light for darker ones
+    #[arg(long, value_name = "THEME")]
+    theme: Option<Theme>,
+
+    /// Never mark this branch as detached, even without a parent. Can be repeated
+    #[arg(long, value_name = "BRANCH")]
+    protect: Vec<String>,
+
+    /// Color of the protected branches, uncolored by default
+    #[arg(long, value_name = "COLOR")]
+    protected_color: Option<HighlightColor>,
+
+    /// Color each level of the tree differently, unless color is disabled
+    #[arg(long)]
+    color_depth: bool,
+
+    /// Don't annotate branches with ahead/behind counts relative to their parent
+    #[arg(long)]
+    no_counts: bool,
+
+    /// Characters used to draw the tree
+    #[arg(long, value_enum, default_value_t = GlyphSet::Unicode)]
+    glyphs: GlyphSet,
+
+    /// Print the tree bottom-up, with children above their parent
+    #[arg(long)]
+    reverse: bool,
+
+    /// Don't print a header before each independent stack
+    #[arg(long)]
+    no_separators: bool,
+
+    /// Hide the descendants of BRANCH, replaced by their number
+    #[arg(long, value_name = "BRANCH")]
+    collapse: Vec<String>,
+
+    /// Print chains of branches with a single child on one line, like A → B → C
+    #[arg(long)]
+    compact: bool,
+
+    /// List the branches that need to be rebased onto the mainline instead of printing the tree
+    #[arg(long)]
+    check: bool,
+
+    /// Print where the children of each branch diverge and how many commits each has since
+    #[arg(long)]
+    fork_points: bool,
+
+    /// Mark the branches whose parent chain is merged into BRANCH, the mainline by default
+    #[arg(long, value_name = "BRANCH")]
+    merge_target: Option<Option<String>>,
+
+    /// Exit with a non-zero status after printing the tree if the condition is met
+    #[arg(long, value_enum, value_name = "CONDITION")]
+    fail_on: Vec<FailCondition>,
+
+    /// Only print the parent of BRANCH, or an empty line if it's a root
+    #[arg(long, value_name = "BRANCH", conflicts_with_all = ["check", "head_only"])]
+    parent_of: Option<String>,
+
+    /// Only print the children of BRANCH, one per line
+    #[arg(long, value_name = "BRANCH", conflicts_with_all = ["check", "head_only", "parent_of"])]
+    children_of: Option<String>,
+
+    /// Warn about the branches with different commits but the same tree, which are likely
+    /// redundant
+    #[arg(long)]
+    same_trees: bool,
+
+    /// Redraw the tree every time a branch moves, until interrupted
+    #[arg(long, conflicts_with_all = ["check", "head_only", "parent_of", "children_of"])]
+    watch: bool,
+
+    /// Only print the chain of branches from the root of the stack down to the current branch
+    #[arg(long)]
+    head_only: bool,
+
+    /// Only print the subtree starting at this branch
+    #[arg(long, value_name = "BRANCH", conflicts_with = "only")]
+    root: Option<String>,
+
+    /// Don't print warnings, errors are still printed
+    #[arg(long, short, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print the progress and timing of each phase to stderr
+    #[arg(long, short)]
+    verbose: bool,
+
+    /// Show the branches whose name isn't valid UTF-8 with the invalid parts replaced, instead of
+    /// skipping them
+    #[arg(long)]
+    lossy_names: bool,
+
+    /// Only print the stack containing the current branch, starting below the mainline
+    #[arg(long, conflicts_with_all = ["only", "root"])]
+    current_stack: bool,
+
+    /// Ignore the branches matching this glob pattern, can be repeated. Mainline branches are never ignored
+    #[arg(long, value_name = "PATTERN", value_parser = parse_pattern)]
+    exclude: Vec<Pattern>,
+
+    /// Only show the structure of the tree: no mainline branches, no protected branches and no
+    /// detached marker
+    #[arg(long, conflicts_with_all = ["base", "protect"])]
+    raw: bool,
+
+    /// Show CHILD under PARENT whatever its detected parent, without recording it. Can be repeated
+    #[arg(long, value_name = "CHILD:PARENT", value_parser = parse_assumed_parent)]
+    assume_parent: Vec<(String, String)>,
+
+    /// Keep the parents detected for the mainline branches instead of always making them roots
+    #[arg(long)]
+    strict_topology: bool,
+
+    /// Fail instead of detecting parents when more than N branches are left after exclusions
+    #[arg(long, value_name = "N")]
+    max_branches: Option<usize>,
+
+    /// Only follow the first parent of merge commits when detecting parents
+    #[arg(long)]
+    first_parent: bool,
+
+    /// Parent chosen between candidates on unrelated lines of history: nearest (default) keeps
+    /// the first one by name, latest picks the one with the most recent tip commit
+    #[arg(long, value_name = "RULE", default_value = "nearest")]
+    parent_rule: ParentRule,
+
+    /// Print the candidate parents of each branch and the chosen one on stderr
+    #[arg(long)]
+    debug_parents: bool,
+
+    /// Print the abbreviated OID of each branch next to its name
+    #[arg(long)]
+    show_oid: bool,
+
+    /// Number of hexadecimal digits of the abbreviated OIDs, defaults to core.abbrev or 7
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(4..=40))]
+    oid_length: Option<u8>,
+
+    /// Truncate the lines of the tree to this many characters, defaults to the terminal width
+    #[arg(long, value_name = "N")]
+    width: Option<usize>,
+
+    /// Mark the current branch if its working tree has uncommitted changes
+    #[arg(long)]
+    dirty: bool,
+
+    /// Annotate branches with the number of commits unique to them
+    #[arg(long)]
+    count: bool,
+
+    /// Annotate branches with the number of files changed, insertions and deletions since their
+    /// parent, down to --depth
+    #[arg(long)]
+    diffstat: bool,
+
+    /// List the commits unique to each branch under it
+    #[arg(long, conflicts_with = "compact")]
+    commits: bool,
+
+    /// Maximum number of commits listed under each branch with --commits
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    commits_limit: usize,
+
+    /// Also show remote-tracking branches
+    #[arg(long)]
+    all: bool,
+}
+
+fn parse_pattern(pattern: &str) -> Result<Pattern, glob::PatternError> {
+    Pattern::new(pattern)
+}
+
+// Parses the --since date into seconds since the Unix epoch.
+fn parse_since(value: &str) -> Result<i64, String> {
+    parse_date(value, now())
+        .ok_or_else(|| "expected a relative date like 2.weeks or an ISO date".to_string())
+}
+
+// Parses a --assume-parent value, git forbids colons in branch names.
+fn parse_assumed_parent(value: &str) -> Result<(String, String), String> {
+    match value.split_once(':') {
+        Some((child, parent)) if !child.is_empty() && !parent.is_empty() => {
+            Ok((child.to_string(), parent.to_string()))
         }
+        _ => Err("expected <child>:<parent>".to_string()),
     }
 }
 
-// Retrieves all local branches in the repository and returns their names and OIDs.
-fn get_branches(repo: &Repository) -> Result<Vec<BranchInfo>, Error> {
-    let mut branches: Vec<BranchInfo> = Vec::new();
-    let mut branch_iter = repo.branches(Some(BranchType::Local))?;
-
-    while let Some(branch_result) = branch_iter.next() {
-        let (branch, _) = branch_result?;
+// Removes the branches matching one of the --exclude patterns or one of the ignored patterns,
+// except the mainline branches.
+fn exclude_branches(
+    cli: &Cli,
+    branches: &mut Vec<BranchInfo>,
+    mainline: &MainlineBranches,
+    ignored: &[Pattern],
+) {
+    branches.retain(|bi| {
+        mainline.contains(&bi.name)
+            || !cli
+                .exclude
+                .iter()
+                .chain(ignored)
+                .any(|pattern| pattern.matches(&bi.name))
+    });
+}
 
-        if let (Some(name_ref), Some(target_oid)) = (branch.name()?, branch.get().target()) {
-            branches.push(BranchInfo {
-                name: name_ref.to_string(),
-                oid: target_oid,
-            });
-        } else if let Ok(name_bytes) = branch.name_bytes() {
+// Prints the candidate parents of each branch with their tip, which is their merge base with
+// the branch, and marks the chosen one.
+fn print_parent_candidates(
+    repo: &Repository,
+    branches: &[BranchInfo],
+    options: &ParentDetectionOptions,
+) -> Result<(), Error> {
+    for pc in get_parent_candidates(repo, branches, options)? {
+        eprintln!("{}", pc.name);
+        if pc.candidates.is_empty() {
+            eprintln!("  no candidate");
+        }
+        for candidate in &pc.candidates {
             eprintln!(
-                "Warning: Branch name could not be processed or is not valid UTF-8: {:?}",
-                String::from_utf8_lossy(name_bytes)
+                "  candidate {} merge-base {}{}",
+                candidate.name,
+                candidate.oid,
+                if pc.parent.as_ref() == Some(&candidate.name) {
+                    " <- parent"
+                } else {
+                    ""
+                }
             );
         }
     }
 
-    Ok(branches)
+    Ok(())
+}
+
+// Returns the current time in seconds since the Unix epoch.
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() as i64)
+}
+
+// Returns the width of the output: --width if given, otherwise the width of the terminal if stdout is
+// one, which is looked up in $COLUMNS if it can't be queried.
+fn output_width(cli: &Cli) -> Option<usize> {
+    if cli.width.is_some() {
+        return cli.width;
+    }
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+
+    match terminal_size::terminal_size() {
+        Some((terminal_size::Width(width), _)) => Some(width as usize),
+        None => std::env::var("COLUMNS").ok()?.parse().ok(),
+    }
+}
+
+// Resolves whether ANSI colors should be emitted: as forced by --color=always, --color=never or
+// --no-color, never when NO_COLOR is set to a non-empty value, never when disabled in the config,
+// and only when stdout is a terminal otherwise, even when enabled in the config.
+fn resolve_color(cli: &Cli, config: &Config) -> ColorChoice {
+    let choice = if cli.no_color {
+        ColorChoice::Never
+    } else {
+        cli.color.unwrap_or(ColorChoice::Auto)
+    };
+    if choice != ColorChoice::Auto {
+        return choice;
+    }
+
+    let enabled = if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        false
+    } else {
+        config.color.unwrap_or(true) && std::io::stdout().is_terminal()
+    };
+    if enabled {
+        ColorChoice::Always
+    } else {
+        ColorChoice::Never
+    }
 }
 
-// Determines the parent-child relationships between branches based on their OIDs.
-fn get_parent_of_relationships(
+// Rebases branch onto new_parent and prints the branches that moved.
+fn run_restack(
     repo: &Repository,
-    branches: &Vec<BranchInfo>,
-) -> Result<ParentOfMap, Error> {
-    let mut parent_of = ParentOfMap(HashMap::new());
+    branch: &str,
+    new_parent: &str,
+    with_descendants: bool,
+) -> Result<(), Error> {
+    print_restacked(&restack(repo, branch, new_parent, with_descendants)?);
 
-    for child_branch_info in branches {
-        let child_name = &child_branch_info.name;
-        let child_oid = child_branch_info.oid;
+    Ok(())
+}
 
-        let mut current_best_parent_name: Option<String> = None;
-        let mut current_best_parent_oid: Option<Oid> = None;
+fn print_restacked(restacked: &[RestackedBranch]) {
+    for rb in restacked {
+        println!(
+            "{}: {} -> {} ({} commit{})",
+            rb.name,
+            rb.old_oid,
+            rb.new_oid,
+            rb.commits,
+            if rb.commits == 1 { "" } else { "s" }
+        );
+    }
+}
 
-        for potential_parent_info in branches {
-            let potential_parent_name = &potential_parent_info.name;
-            let potential_parent_oid = potential_parent_info.oid;
+fn run_sync(
+    cli: &Cli,
+    repo: &Repository,
+    dry_run: bool,
+    continue_: bool,
+    abort: bool,
+) -> Result<(), Error> {
+    let synced = if abort {
+        return abort_sync(repo);
+    } else if continue_ {
+        continue_sync(repo)?
+    } else {
+        let config = Config::load_for_repo(repo)?;
+        let mainline = resolve_mainline(cli, repo, &config, &get_branches(repo)?)?;
+        let steps = plan_sync(repo, &mainline)?;
 
-            if child_name == potential_parent_name || potential_parent_oid == child_oid {
-                continue;
+        if dry_run {
+            for step in &steps {
+                println!(
+                    "rebase {} onto {} (from {})",
+                    step.branch, step.onto, step.upstream_oid
+                );
             }
+            return Ok(());
+        }
 
-            match repo.merge_base(potential_parent_oid, child_oid) {
-                Ok(base_oid) if base_oid == potential_parent_oid => {
-                    // potential_parent is an ancestor
-                    if current_best_parent_name.is_none() {
-                        current_best_parent_name = Some(potential_parent_name.clone());
-                        current_best_parent_oid = Some(potential_parent_oid);
-                    } else if let Some(cbp_oid) = current_best_parent_oid {
-                        if cbp_oid != potential_parent_oid {
-                            // Ensure we are looking at a different commit
-                            match repo.merge_base(cbp_oid, potential_parent_oid) {
-                                Ok(base_between_parents_oid)
-                                    if base_between_parents_oid == cbp_oid =>
-                                {
-                                    // cbp_oid is an ancestor of potential_parent_oid,
-                                    // meaning potential_parent is more specific/descendant.
-                                    current_best_parent_name = Some(potential_parent_name.clone());
-                                    current_best_parent_oid = Some(potential_parent_oid);
-                                }
-                                Err(e) if e.code() == ErrorCode::NotFound => { /* No common base, not ordered */
-                                }
-                                Err(e) => return Err(Error::Git2(e)),
-                                _ => {}
-                            }
-                        }
-                    }
-                }
-                Err(e) if e.code() == ErrorCode::NotFound => { /* No common base */ }
-                Err(e) => return Err(Error::Git2(e)),
-                _ => {} // Not an ancestor
-            }
+        sync(repo, &steps)?
+    };
+
+    print_restacked(&synced);
+
+    Ok(())
+}
+
+fn run_prune(cli: &Cli, repo: &Repository, force: bool) -> Result<(), Error> {
+    let config = Config::load_for_repo(repo)?;
+    let mainline = resolve_mainline(cli, repo, &config, &get_branches(repo)?)?;
+
+    let mut merged = get_merged_branches(repo, &mainline)?;
+    exclude_branches(cli, &mut merged, &mainline, &[]);
+    if merged.is_empty() {
+        return Ok(());
+    }
+
+    if force {
+        delete_branches(repo, &merged)?;
+        for bi in &merged {
+            println!("deleted {} (was {})", bi.name, bi.oid);
         }
-        if let Some(p_name) = current_best_parent_name {
-            parent_of.0.insert(child_name.clone(), p_name);
+    } else {
+        for bi in &merged {
+            println!("would delete {} ({})", bi.name, bi.oid);
         }
+        eprintln!("run with --force to delete them");
     }
 
-    Ok(parent_of)
+    Ok(())
 }
 
-struct ChildrenAndRoots {
-    children_map: ChildrenMap,
-    roots: Vec<String>,
+// Prints the name of every branch, including the remote ones with --all.
+fn run_list_branches(cli: &Cli, repo: &Repository) -> Result<(), Error> {
+    let mut branches = get_branches(repo)?;
+    if cli.all {
+        branches.extend(get_remote_branches(repo)?);
+    }
+
+    for bi in branches {
+        println!("{}", bi.name);
+    }
+
+    Ok(())
 }
 
-// Builds the children_map and identifies root branches based on parent-child relationships.
-fn build_children_and_roots(
-    branches: &Vec<BranchInfo>,
-    parent_of: &ParentOfMap,
-) -> Result<ChildrenAndRoots, Error> {
-    let mut children_map = ChildrenMap(BTreeMap::new());
+// Prints a report of the mainline, the detected parents and the problems found across the
+// branches: ambiguous parents and cycles. Branches sharing a commit are listed too.
+fn run_doctor(cli: &Cli, repo: &Repository) -> Result<(), Error> {
+    let config = Config::load_for_repo(repo)?;
+    let mut branches = get_branches(repo)?;
+    let mainline = resolve_mainline(cli, repo, &config, &branches)?;
+    exclude_branches(cli, &mut branches, &mainline, &get_ignore_patterns(repo)?);
 
-    let mut all_branch_names: HashSet<String> = HashSet::new();
-    for bi in branches {
-        all_branch_names.insert(bi.name.clone());
+    match mainline.find(&branches) {
+        Some(bi) => println!("mainline: {}", bi.name),
+        None => println!("mainline: none found, looked for {}", mainline.0.join(", ")),
     }
 
-    let mut children_with_parents: HashSet<String> = HashSet::new();
-    for (child, parent) in &parent_of.0 {
-        children_map
-            .0
-            .entry(parent.clone())
-            .or_default()
-            .push(child.clone());
-        children_with_parents.insert(child.clone());
+    let aliases = group_aliases(&mut branches, &mainline);
+    let options = ParentDetectionOptions {
+        first_parent: cli.first_parent,
+        rule: cli.parent_rule,
+    };
+    let (parent_of, warnings) =
+        get_parent_of_relationships_with_warnings(repo, &branches, &options)?;
+    let configured = get_configured_parents(repo, &branches)?;
+
+    println!(
+        "branches: {}, {} with a parent ({} recorded in the git config), {} roots",
+        branches.len(),
+        parent_of.0.len(),
+        configured.0.len(),
+        branches.len() - parent_of.0.len()
+    );
+
+    let mut problems = 0;
+
+    for warning in &warnings {
+        println!(
+            "ambiguous parent: {} has unrelated candidate parents {} and {}, picked {}",
+            warning.name, warning.parent, warning.other, warning.parent
+        );
+        problems += 1;
     }
 
-    // Sort children within each parent's list for deterministic output
-    for children in children_map.0.values_mut() {
-        children.sort();
+    if let Some(cycle) = find_cycle(&parent_of) {
+        println!("cycle: {} -> {}", cycle.join(" -> "), cycle[0]);
+        problems += 1;
     }
 
-    let mut roots: Vec<String> = all_branch_names
-        .difference(&children_with_parents)
-        .cloned()
-        .collect();
-    roots.sort(); // Sort roots for deterministic output
+    let mut representatives: Vec<_> = aliases.0.iter().collect();
+    representatives.sort();
+    for (name, others) in representatives {
+        println!("same commit: {}, {}", name, others.join(", "));
+    }
 
-    Ok(ChildrenAndRoots {
-        children_map,
-        roots,
-    })
+    if problems == 0 {
+        println!("no problem found");
+    }
+
+    Ok(())
 }
 
-// Prints the branch tree structure based on the branches, parent-child relationships, and roots.
-fn print_tree(
-    branches: &Vec<BranchInfo>,
-    parent_of: &ParentOfMap,
-    children_map: &ChildrenMap,
-    roots: &Vec<String>,
-) -> Result<(), Error> {
-    let mainline_branch_names: HashSet<&str> =
-        MAINLINE_BRANCH_NAMES_ARRAY.iter().cloned().collect();
+// Clears the terminal and moves the cursor to the top left corner
+const CLEAR_SCREEN: &str = "\x1B[2J\x1B[H";
 
-    if roots.is_empty() && !branches.is_empty() {
-        if !&parent_of.0.is_empty() {
-            // Structure exists but no clear roots (e.g. cycle, though unlikely)
-            eprintln!(
-                "Warning: Could not determine clear root(s) for branch tree. Check for unusual branch structures."
+// How often --watch checks whether the branches changed
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+// Makes the cursor visible again, in case it was hidden while the tree was redrawn
+const SHOW_CURSOR: &str = "\x1B[?25h";
+
+// Set once --watch is interrupted, so that it can stop and restore the terminal
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Records an interruption in INTERRUPTED instead of terminating the process. Elsewhere than on
+// Unix the process is still terminated.
+fn catch_interrupts() {
+    #[cfg(unix)]
+    {
+        extern "C" fn on_interrupt(_: libc::c_int) {
+            INTERRUPTED.store(true, Ordering::SeqCst);
+        }
+
+        // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+        unsafe {
+            libc::signal(
+                libc::SIGINT,
+                on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t,
             );
-            for bi in branches {
-                // Fallback: print all branches flatly
-                println!("{}", bi.name);
-            }
-        } else {
-            // No parents found, all branches are effectively roots
-            for bi in branches {
-                let display_name = if mainline_branch_names.contains(bi.name.as_str()) {
-                    bi.name.clone()
-                } else {
-                    format!(
-                        "{}{}{} {}",
-                        RED_START, DETACHED_PREFIX_TEXT, COLOR_RESET, bi.name
-                    )
-                };
-                println!("{}", display_name);
-                // children_map for this branch would be empty or not exist
-                print_ascii_tree_recursive(&bi.name, &children_map, "");
-            }
         }
-        return Ok(());
     }
+}
 
-    for root_branch_name in roots {
-        let display_name = if mainline_branch_names.contains(root_branch_name.as_str()) {
-            root_branch_name.clone()
-        } else {
-            format!(
-                "{}{}{} {}",
-                RED_START, DETACHED_PREFIX_TEXT, COLOR_RESET, root_branch_name
-            )
-        };
-        println!("{}", display_name);
-        print_ascii_tree_recursive(root_branch_name, &children_map, "");
+// Redraws the tree whenever a branch, the current branch, a recorded parent or, with --dirty,
+// the state of the working tree changes. Stops when the process is interrupted.
+//
+// Errors don't stop watching since they can be transient, for example in the middle of a rebase.
+fn run_watch(cli: &Cli, repo: &Repository) -> Result<(), Error> {
+    let snapshot = || -> Result<_, Error> {
+        let branches = get_branches(repo)?;
+        let mut configured_parents: Vec<_> = get_configured_parents(repo, &branches)?
+            .0
+            .into_iter()
+            .collect();
+        configured_parents.sort();
+        let branches: Vec<_> = branches.into_iter().map(|bi| (bi.name, bi.oid)).collect();
+        let dirty = cli.dirty && has_uncommitted_changes(repo)?;
+
+        Ok((
+            branches,
+            get_current_branch(repo)?,
+            configured_parents,
+            dirty,
+        ))
+    };
+
+    catch_interrupts();
+
+    // None until the first frame is drawn
+    let mut previous = None;
+    while !INTERRUPTED.load(Ordering::SeqCst) {
+        let current = snapshot().ok();
+        if previous.as_ref() != Some(&current) {
+            print!("{}", CLEAR_SCREEN);
+            // The screen is cleared before an error is printed on stderr
+            std::io::stdout().flush()?;
+            if let Err(err) = show_tree(cli, repo) {
+                eprintln!("git-stacked: {}", err);
+            }
+            std::io::stdout().flush()?;
+            previous = Some(current);
+        }
+
+        std::thread::sleep(WATCH_INTERVAL);
     }
 
+    print!("{}", SHOW_CURSOR);
+    std::io::stdout().flush()?;
+
     Ok(())
 }
 
-fn do_it() -> Result<(), Error> {
-    let repo_path = Repository::discover(".")?
-        .workdir()
-        .ok_or(Error::RepositoryIsBare)?
-        .to_path_buf();
-    let repo = Repository::open(repo_path)?;
+// Prints the branches forked from an older commit of the mainline, fails if there are any.
+fn run_check(cli: &Cli, repo: &Repository) -> Result<(), Error> {
+    let config = Config::load_for_repo(repo)?;
+    let mut branches = get_branches(repo)?;
+    let mainline = resolve_mainline(cli, repo, &config, &branches)?;
+    exclude_branches(cli, &mut branches, &mainline, &get_ignore_patterns(repo)?);
+
+    let stale = get_stale_branches(repo, &branches, &mainline)?;
+    for sb in &stale {
+        println!(
+            "{}: mainline is {} commit{} ahead",
+            sb.name,
+            sb.behind,
+            if sb.behind == 1 { "" } else { "s" }
+        );
+    }
+
+    if stale.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::StaleBranches(stale.len()))
+    }
+}
+
+// Returns the mainline branches: only --base if given, otherwise the configured ones.
+//
+// The default branch of the origin remote replaces the built-in mainline names, if it exists locally.
+fn resolve_mainline(
+    cli: &Cli,
+    repo: &Repository,
+    config: &Config,
+    branches: &[BranchInfo],
+) -> Result<MainlineBranches, Error> {
+    match &cli.base {
+        Some(base) => {
+            if !branches.iter().any(|bi| bi.name == *base) {
+                return Err(Error::BranchNotFound(base.clone()));
+            }
+            Ok(MainlineBranches(vec![base.clone()]))
+        }
+        None => {
+            let default_branch =
+                get_default_branch(repo)?.filter(|name| branches.iter().any(|bi| bi.name == *name));
+            Ok(config.mainline_branches_with_default(default_branch.as_deref()))
+        }
+    }
+}
+
+fn do_it(cli: &Cli, path: &Path) -> Result<(), Error> {
+    let repo = open_repository(path)?;
+
+    match &cli.command {
+        Some(Command::Restack {
+            branch,
+            onto,
+            descendants,
+        }) => run_restack(&repo, branch, onto, *descendants),
+        Some(Command::Prune { force, .. }) => run_prune(cli, &repo, *force),
+        Some(Command::Doctor) => run_doctor(cli, &repo),
+        Some(Command::ListBranches) => run_list_branches(cli, &repo),
+        Some(Command::SetParent { branch, parent }) => {
+            set_parent(&repo, branch, parent)?;
+            show_tree(cli, &repo)
+        }
+        Some(Command::Rename { old, new }) => {
+            rename_branch(&repo, old, new)?;
+            show_tree(cli, &repo)
+        }
+        Some(Command::Sync {
+            dry_run,
+            continue_,
+            abort,
+        }) => run_sync(cli, &repo, *dry_run, *continue_, *abort),
+        None if cli.check => run_check(cli, &repo),
+        None if cli.watch => run_watch(cli, &repo),
+        None => show_tree(cli, &repo),
+    }
+}
+
+// Prints the branch tree of the repository.
+fn show_tree(cli: &Cli, repo: &Repository) -> Result<(), Error> {
+    let config = Config::load_for_repo(repo)?;
+
+    let current_branch = get_current_branch(repo)?;
+    // Computing the status can be slow on large working trees, only do it when asked
+    let dirty = cli.dirty && current_branch.is_some() && has_uncommitted_changes(repo)?;
+    let worktree_branches = get_worktree_branches(repo)?;
+
+    // Remote-tracking branches are updated before anything reads them
+    if cli.fetch {
+        for remote in get_upstream_remotes(repo, &get_branches(repo)?)? {
+            let start = Instant::now();
+            fetch_remote(repo, &remote)?;
+            info(format_args!("fetched {} in {:?}", remote, start.elapsed()));
+        }
+    }
 
     // 1. Get local branches info (name and OID)
-    let mut branches = get_branches(&repo)?;
+    let start = Instant::now();
+    let mut branches = get_branches_with_options(
+        repo,
+        &BranchOptions {
+            lossy_names: cli.lossy_names,
+        },
+    )?;
+
+    // Remote branches come last: when a local and a remote branch share a tip, the local
+    // one is picked as the parent of the branches stacked on it.
+    if cli.all {
+        branches.extend(get_remote_branches(repo)?);
+    }
 
-    // Sort branch names for deterministic processing
-    branches.sort_by(|a, b| a.name.cmp(&b.name));
+    info(format_args!(
+        "found {} branches in {:?}",
+        branches.len(),
+        start.elapsed()
+    ));
 
     if branches.is_empty() {
+        // Machine-readable formats stay empty
+        if cli.format == Format::Ascii && !cli.porcelain && is_empty_repository(repo)? {
+            println!("no commits yet");
+        }
         return Ok(());
     }
 
+    // The tip commits are looked up once for all the features needing their time or summary
+    let commit_logs = if cli.log || cli.sort == SortOrder::Date || cli.since.is_some() {
+        Some(get_commit_logs(repo, &branches)?)
+    } else {
+        None
+    };
+
+    // The raw tree has no mainline branches, so none of them is special-cased
+    let mainline = if cli.raw {
+        MainlineBranches(Vec::new())
+    } else {
+        resolve_mainline(cli, repo, &config, &branches)?
+    };
+    if !cli.raw && mainline.find(&branches).is_none() {
+        warn(format_args!(
+            "no mainline branch found (looked for {}), all roots are shown as detached, use --base to set one",
+            mainline.0.join(", ")
+        ));
+    }
+
+    // Excluded branches are dropped before parent detection, so they can't be parents either
+    exclude_branches(cli, &mut branches, &mainline, &get_ignore_patterns(repo)?);
+    if let (Some(since), Some(commit_logs)) = (cli.since, &commit_logs) {
+        remove_inactive_branches(&mut branches, &mainline, commit_logs, since);
+    }
+    // The current branch can't be marked, nor its stack selected, once filtered out
+    let current_branch_hidden = match &current_branch {
+        Some(name) if !branches.iter().any(|bi| bi.name == *name) => {
+            warn(format_args!(
+                "the current branch {} is hidden by --exclude, --since or the ignore file",
+                name
+            ));
+            true
+        }
+        _ => false,
+    };
+    if let Some(max_branches) = cli.max_branches
+        && branches.len() > max_branches
+    {
+        return Err(Error::TooManyBranches(branches.len(), max_branches));
+    }
+
+    // Branches pointing at the same commit are shown together
+    let aliases = group_aliases(&mut branches, &mainline);
+
+    let detection_options = ParentDetectionOptions {
+        first_parent: cli.first_parent,
+        rule: cli.parent_rule,
+    };
+
+    if cli.debug_parents {
+        print_parent_candidates(repo, &branches, &detection_options)?;
+    }
+
     // 2. Determine parent_of relationships
-    let parent_of = get_parent_of_relationships(&repo, &branches)?;
+    let start = Instant::now();
+    let (mut parent_of, parent_warnings) =
+        get_parent_of_relationships_with_warnings(repo, &branches, &detection_options)?;
+    info(format_args!(
+        "detected {} parents among {} branches in {:?}",
+        parent_of.0.len(),
+        branches.len(),
+        start.elapsed()
+    ));
+
+    // An explicit base is always the root of its stack
+    if let Some(base) = &cli.base {
+        parent_of.0.remove(base);
+    }
+    if !cli.strict_topology {
+        detach_mainline(&mut parent_of, &mainline);
+    }
+
+    // Assumed parents win over everything else
+    let assumed: Vec<(String, String)> = cli
+        .assume_parent
+        .iter()
+        .map(|(child, parent)| {
+            (
+                aliases.representative(child).to_string(),
+                aliases.representative(parent).to_string(),
+            )
+        })
+        .collect();
+    assume_parents(&mut parent_of, &branches, &assumed)?;
+
+    if let Some(branch) = &cli.parent_of {
+        let branch = aliases.representative(branch);
+        if !branches.iter().any(|bi| bi.name == branch) {
+            return Err(Error::BranchNotFound(branch.to_string()));
+        }
+
+        println!("{}", parent_of.0.get(branch).map_or("", String::as_str));
+        return Ok(());
+    }
+
+    // With --fail-on cycle the rest of the tree is printed before failing
+    let cycles = if cli.fail_on.contains(&FailCondition::Cycle) {
+        remove_cycles(&mut branches, &mut parent_of)
+    } else {
+        Vec::new()
+    };
 
     // 3. Build children_map (sorted by key for consistent iteration order) and identify roots
+    let mut stack = build_children_and_roots(&branches, &parent_of)?;
+    let start = Instant::now();
+
+    if let Some(branch) = &cli.children_of {
+        let branch = aliases.representative(branch);
+        if !branches.iter().any(|bi| bi.name == branch) {
+            return Err(Error::BranchNotFound(branch.to_string()));
+        }
+
+        // Branches sharing the commit of a child are children too
+        let mut children: Vec<&str> = stack
+            .children_map
+            .0
+            .get(branch)
+            .into_iter()
+            .flatten()
+            .flat_map(|child| {
+                std::iter::once(child)
+                    .chain(aliases.0.get(child).into_iter().flatten())
+                    .map(String::as_str)
+            })
+            .collect();
+        children.sort();
+
+        for child in children {
+            println!("{}", child);
+        }
+        return Ok(());
+    }
+
+    if cli.head_only {
+        let Some(current_branch) = &current_branch else {
+            println!("HEAD is detached");
+            return Ok(());
+        };
+
+        // The path ends at the branch standing for the current one, but shows its actual name
+        let mut path = vec![current_branch.as_str()];
+        let mut name = aliases.representative(current_branch);
+        while let Some(parent) = parent_of.0.get(name) {
+            path.push(parent);
+            name = parent;
+        }
+        path.reverse();
+
+        print_head_path(&path, cli.glyphs.glyphs());
+        return Ok(());
+    }
+
+    if cli.count_only {
+        return print_counts(
+            &branches,
+            &aliases,
+            &stack.children_map,
+            &stack.roots,
+            cli.format == Format::Json,
+        );
+    }
+
+    let ahead_behind = if cli.no_counts && cli.sort != SortOrder::Ahead {
+        None
+    } else {
+        Some(get_ahead_behind_counts(
+            repo, &branches, &parent_of, &mainline,
+        )?)
+    };
+
+    let oid_length = match cli.oid_length {
+        Some(oid_length) => oid_length as usize,
+        None => get_abbrev_length(repo)?.unwrap_or(DEFAULT_ABBREV_LENGTH),
+    };
+
+    let short_oids: Option<HashMap<String, String>> = if cli.show_oid {
+        Some(
+            branches
+                .iter()
+                .map(|bi| {
+                    let mut short_oid = bi.oid.to_string();
+                    short_oid.truncate(oid_length);
+                    (bi.name.clone(), short_oid)
+                })
+                .collect(),
+        )
+    } else {
+        None
+    };
+
+    let commit_counts = if cli.count {
+        Some(get_commit_counts(repo, &branches, &parent_of, &mainline)?)
+    } else {
+        None
+    };
+
+    let diff_stats = if cli.diffstat {
+        Some(get_diff_stats(
+            repo, &branches, &parent_of, &mainline, cli.depth,
+        )?)
+    } else {
+        None
+    };
+
+    let branch_commits = if cli.commits {
+        Some(get_branch_commits(
+            repo,
+            &branches,
+            &parent_of,
+            &mainline,
+            cli.commits_limit,
+        )?)
+    } else {
+        None
+    };
+
+    let merge_readiness = match &cli.merge_target {
+        Some(target) => {
+            let target = match target {
+                Some(target) => {
+                    let target = aliases.representative(target);
+                    branches
+                        .iter()
+                        .find(|bi| bi.name == target)
+                        .ok_or_else(|| Error::BranchNotFound(target.to_string()))?
+                }
+                None => mainline
+                    .find(&branches)
+                    .ok_or_else(|| Error::BranchNotFound(mainline.0.join(", ")))?,
+            };
+            Some((
+                target.name.clone(),
+                get_merge_readiness(repo, &branches, &parent_of, &mainline, target)?,
+            ))
+        }
+        None => None,
+    };
+
+    let fork_points = if cli.fork_points {
+        Some(get_fork_points(repo, &branches, &stack.children_map)?)
+    } else {
+        None
+    };
+
+    let upstream_statuses = if cli.remote {
+        Some(get_upstream_statuses(repo, &branches)?)
+    } else {
+        None
+    };
+
+    match (cli.sort, &ahead_behind, &commit_logs) {
+        (SortOrder::Date, _, Some(commit_logs)) => stack
+            .sort_by_key(|name| Reverse(commit_logs.0.get(name).map_or(i64::MIN, |log| log.time))),
+        (SortOrder::Ahead, Some(ahead_behind), _) => stack
+            .sort_by_key(|name| Reverse(ahead_behind.0.get(name).map_or(0, |(ahead, _)| *ahead))),
+        (SortOrder::Topo, _, _) => {
+            let topo_order = get_topo_order(repo, &branches, &parent_of, &mainline)?;
+            stack.sort_by_key(|name| {
+                topo_order
+                    .0
+                    .get(name)
+                    .copied()
+                    .unwrap_or((i64::MAX, i64::MAX))
+            })
+        }
+        _ => {}
+    }
+
+    if let Some(only) = &cli.only {
+        let only = aliases.representative(only);
+        if !branches.iter().any(|bi| bi.name == only) {
+            return Err(Error::BranchNotFound(only.to_string()));
+        }
+
+        let mut keep: HashSet<String> = get_ancestors(only, &parent_of).into_iter().collect();
+        keep.extend(get_descendants(only, &stack.children_map));
+        keep.insert(only.to_string());
+
+        branches.retain(|bi| keep.contains(&bi.name));
+        stack.retain(&keep);
+    }
+
+    // The stack of the current branch starts below the mainline, unless it's a mainline branch
+    let current_stack_root = match (cli.current_stack, &current_branch) {
+        (true, Some(_)) if current_branch_hidden => {
+            warn("no current stack to show, printing all the stacks");
+            None
+        }
+        (true, Some(current_branch)) => {
+            let mut root = aliases.representative(current_branch);
+            while let Some(parent) = parent_of.0.get(root)
+                && !mainline.contains(parent)
+            {
+                root = parent;
+            }
+            Some(root.to_string())
+        }
+        (true, None) => {
+            warn("HEAD is detached, printing all the stacks");
+            None
+        }
+        (false, _) => None,
+    };
+
+    if let Some(root) = cli.root.as_ref().or(current_stack_root.as_ref()) {
+        let root = aliases.representative(root);
+        if !branches.iter().any(|bi| bi.name == root) {
+            return Err(Error::BranchNotFound(root.to_string()));
+        }
+
+        let mut keep: HashSet<String> = get_descendants(root, &stack.children_map)
+            .into_iter()
+            .collect();
+        keep.insert(root.to_string());
+
+        branches.retain(|bi| keep.contains(&bi.name));
+        stack.retain(&keep);
+        stack.roots = vec![root.to_string()];
+    }
+
     let ChildrenAndRoots {
         children_map,
         roots,
-    } = build_children_and_roots(&branches, &parent_of)?;
+    } = stack;
+
+    let root_authors = match cli.group_by {
+        Some(GroupBy::Author) => {
+            let root_branches: Vec<BranchInfo> = branches
+                .iter()
+                .filter(|bi| roots.contains(&bi.name))
+                .cloned()
+                .collect();
+            Some(get_tip_authors(repo, &root_branches)?)
+        }
+        None => None,
+    };
+
+    let collapsed: HashSet<String> = cli
+        .collapse
+        .iter()
+        .map(|name| aliases.representative(name).to_string())
+        .collect();
+
+    let protected: HashSet<String> = if cli.raw {
+        HashSet::new()
+    } else {
+        cli.protect
+            .iter()
+            .chain(&config.protected_branches)
+            .cloned()
+            .collect()
+    };
+
+    let theme = cli.theme.or(config.theme).unwrap_or_default();
+
+    let options = RenderOptions {
+        current_branch: current_branch.as_deref(),
+        dirty,
+        worktree_branches: &worktree_branches,
+        aliases: &aliases,
+        short_oids: short_oids.as_ref(),
+        ahead_behind: ahead_behind.as_ref().filter(|_| !cli.no_counts),
+        commit_counts: commit_counts.as_ref(),
+        diff_stats: diff_stats.as_ref(),
+        merge_readiness: merge_readiness.as_ref().map(|(_, readiness)| readiness),
+        fork_points: fork_points.as_ref(),
+        oid_length,
+        color: resolve_color(cli, &config) == ColorChoice::Always,
+        theme,
+        color_depth: cli.color_depth,
+        detached_label: cli
+            .detached_label
+            .as_deref()
+            .or(config.detached_label.as_deref())
+            .unwrap_or(DEFAULT_DETACHED_LABEL),
+        detached_color: cli
+            .detached_color
+            .or(config.detached_color)
+            .unwrap_or(HighlightColor::Red),
+        mainline_color: cli
+            .mainline_color
+            .or(config.mainline_color)
+            .unwrap_or(theme.default_mainline_color()),
+        protected_color: cli.protected_color.or(config.protected_color),
+        mainline: &mainline,
+        protected: &protected,
+        max_depth: cli.depth,
+        compact: cli.compact,
+        root_authors: root_authors.as_ref(),
+        collapsed: &collapsed,
+        glyphs: cli.glyphs.glyphs(),
+        reverse: cli.reverse,
+        separators: !cli.no_separators,
+        upstream_statuses: upstream_statuses.as_ref(),
+        commit_logs: commit_logs.as_ref().filter(|_| cli.log),
+        branch_commits: branch_commits.as_ref(),
+        log_width: cli.log_width,
+        now: now(),
+        width: output_width(cli),
+        strip_prefix: cli.strip_prefix.as_deref(),
+        mark_detached: !cli.raw,
+    };
+
+    info(format_args!(
+        "computed the annotations in {:?}",
+        start.elapsed()
+    ));
 
     // 4. Handle edge cases for printing & actual printing
-    print_tree(&branches, &parent_of, &children_map, &roots)?;
+    let start = Instant::now();
+    let format = if cli.porcelain {
+        Format::Porcelain
+    } else {
+        cli.format
+    };
+    format.render(&branches, &parent_of, &children_map, &roots, &options)?;
+    info(format_args!("rendered the tree in {:?}", start.elapsed()));
+
+    if cli.stats {
+        print_stats(
+            &branches,
+            &aliases,
+            &parent_of,
+            &children_map,
+            &roots,
+            &mainline,
+            &protected,
+        );
+    }
+
+    if let Some(cycle) = cycles.into_iter().next() {
+        return Err(Error::CycleDetected(cycle));
+    }
+    if cli.fail_on.contains(&FailCondition::Detached) {
+        let detached = detached_roots(&roots, &parent_of, &mainline, &protected).len();
+        if detached > 0 {
+            return Err(Error::DetachedBranches(detached));
+        }
+    }
+    if cli.fail_on.contains(&FailCondition::Stale) {
+        let stale = get_stale_branches(repo, &branches, &mainline)?.len();
+        if stale > 0 {
+            return Err(Error::StaleBranches(stale));
+        }
+    }
+
+    let printed: HashSet<&str> = branches.iter().map(|bi| bi.name.as_str()).collect();
+
+    if let (Format::Ascii, Some((target, readiness))) = (cli.format, &merge_readiness) {
+        let (ready, total) = readiness
+            .0
+            .iter()
+            .filter(|(name, _)| printed.contains(name.as_str()))
+            .fold((0, 0), |(ready, total), (_, is_ready)| {
+                (ready + usize::from(*is_ready), total + 1)
+            });
+        println!();
+        println!(
+            "{} of {} branches ready to merge into {}",
+            ready, total, target
+        );
+    }
+
+    if cli.same_trees {
+        for group in get_identical_trees(repo, &branches)? {
+            warn(format_args!("{} have the same tree", group.join(", ")));
+        }
+    }
+
+    // Only warn about the branches that were printed
+    for warning in parent_warnings
+        .iter()
+        .filter(|warning| printed.contains(warning.name.as_str()))
+    {
+        warn(format_args!(
+            "{} has unrelated candidate parents {} and {}, picked {}",
+            warning.name, warning.parent, warning.other, warning.parent
+        ));
+    }
 
     Ok(())
 }
 
+// Maps an error to the exit status of the process, so that scripts can tell failures apart.
+// 2 is left to clap for usage errors.
+fn exit_code(err: &Error) -> i32 {
+    match err {
+        Error::NotARepository => 3,
+        Error::RepositoryIsBare => 4,
+        Error::InvalidConfig(..) | Error::InvalidIgnoreFile(..) => 5,
+        Error::BranchNotFound(_) | Error::InvalidBranchName(_) | Error::BranchExists(_) => 6,
+        Error::CycleDetected(_) => 7,
+        Error::DirtyWorkingTree => 8,
+        Error::InvalidRestack(..) | Error::NoCommonHistory(..) => 9,
+        Error::RebaseConflict(..) => 10,
+        Error::StaleBranches(_) => 11,
+        Error::DetachedBranches(_) => 12,
+        Error::TooManyBranches(..) => 13,
+        Error::SyncConflict(..) => 10,
+        Error::NoSyncInProgress | Error::InvalidSyncState(_) => 1,
+        Error::Git2(_) | Error::Io(_) | Error::Json(_) | Error::FetchFailed(..) => 1,
+    }
+}
+
 fn main() {
-    do_it().unwrap()
+    let cli = Cli::parse();
+    set_verbosity(if cli.quiet {
+        Verbosity::Quiet
+    } else if cli.verbose {
+        Verbosity::Verbose
+    } else {
+        Verbosity::Normal
+    });
+
+    // An absolute path replaces the previous ones when joined
+    let start_dir: PathBuf = cli.change_dir.iter().collect();
+
+    if let [path] = cli.repo.as_slice() {
+        if let Err(err) = do_it(&cli, &start_dir.join(path)) {
+            eprintln!("git-stacked: {}", err);
+            std::process::exit(exit_code(&err));
+        }
+        return;
+    }
+
+    if cli.command.is_some() || cli.watch || cli.check {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "several --repo can only be given to print trees",
+            )
+            .exit();
+    }
+
+    // An error in one repository doesn't prevent printing the others, the first one sets the
+    // exit code
+    let glyphs = cli.glyphs.glyphs();
+    let mut code = 0;
+    for (i, path) in cli.repo.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{} {} {}", glyphs.rule, path.display(), glyphs.rule);
+
+        if let Err(err) = do_it(&cli, &start_dir.join(path)) {
+            eprintln!("git-stacked: {}: {}", path.display(), err);
+            if code == 0 {
+                code = exit_code(&err);
+            }
+        }
+    }
+    if code != 0 {
+        std::process::exit(code);
+    }
 }
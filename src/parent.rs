@@ -0,0 +1,30 @@
+use crate::{
+    Error, STACKED_PARENT_CONFIG_KEY, find_cycle, get_branches, get_parent_of_relationships,
+};
+use git2::Repository;
+
+// Records parent_name as the parent of branch_name in branch.<name>.stacked-parent.
+//
+// Both branches must exist, and the new relationship must not create a cycle with the recorded
+// or inferred parents of the other branches.
+pub fn set_parent(repo: &Repository, branch_name: &str, parent_name: &str) -> Result<(), Error> {
+    let branches = get_branches(repo)?;
+    for name in [branch_name, parent_name] {
+        if !branches.iter().any(|bi| bi.name == name) {
+            return Err(Error::BranchNotFound(name.to_string()));
+        }
+    }
+
+    let mut parent_of = get_parent_of_relationships(repo, &branches)?;
+    parent_of
+        .0
+        .insert(branch_name.to_string(), parent_name.to_string());
+    if let Some(cycle) = find_cycle(&parent_of) {
+        return Err(Error::CycleDetected(cycle));
+    }
+
+    let key = format!("branch.{}.{}", branch_name, STACKED_PARENT_CONFIG_KEY);
+    repo.config()?.set_str(&key, parent_name)?;
+
+    Ok(())
+}
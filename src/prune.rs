@@ -0,0 +1,45 @@
+use crate::{BranchInfo, Error, MainlineBranches, get_branches, get_current_branch};
+use git2::{BranchType, Repository};
+
+// Returns the branches fully merged into one of the mainline branches, that is whose tip is
+// the tip of a mainline branch or one of its ancestors.
+//
+// The mainline branches themselves and the current branch are never returned.
+pub fn get_merged_branches(
+    repo: &Repository,
+    mainline: &MainlineBranches,
+) -> Result<Vec<BranchInfo>, Error> {
+    let branches = get_branches(repo)?;
+    let current_branch = get_current_branch(repo)?;
+
+    let mainline_oids: Vec<_> = branches
+        .iter()
+        .filter(|bi| mainline.contains(&bi.name))
+        .map(|bi| bi.oid)
+        .collect();
+
+    let mut merged = Vec::new();
+    for bi in &branches {
+        if mainline.contains(&bi.name) || current_branch.as_deref() == Some(bi.name.as_str()) {
+            continue;
+        }
+
+        for mainline_oid in &mainline_oids {
+            if bi.oid == *mainline_oid || repo.graph_descendant_of(*mainline_oid, bi.oid)? {
+                merged.push(bi.clone());
+                break;
+            }
+        }
+    }
+
+    Ok(merged)
+}
+
+// Deletes the given local branches.
+pub fn delete_branches(repo: &Repository, branches: &[BranchInfo]) -> Result<(), Error> {
+    for bi in branches {
+        repo.find_branch(&bi.name, BranchType::Local)?.delete()?;
+    }
+
+    Ok(())
+}
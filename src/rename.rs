@@ -0,0 +1,40 @@
+use crate::{Error, STACKED_PARENT_CONFIG_KEY, get_branches};
+use git2::{Branch, BranchType, ErrorCode, Repository};
+
+// Renames the local branch old_name to new_name. The new name must be valid and not taken by
+// another branch.
+//
+// The branch.<name>.* settings of the git config follow the branch, as do HEAD and the reflog.
+// Branches recording old_name as their parent are updated to record new_name.
+pub fn rename_branch(repo: &Repository, old_name: &str, new_name: &str) -> Result<(), Error> {
+    if !Branch::name_is_valid(new_name)? {
+        return Err(Error::InvalidBranchName(new_name.to_string()));
+    }
+
+    let mut branch = match repo.find_branch(old_name, BranchType::Local) {
+        Ok(branch) => branch,
+        Err(e) if e.code() == ErrorCode::NotFound => {
+            return Err(Error::BranchNotFound(old_name.to_string()));
+        }
+        Err(e) => return Err(Error::Git2(e)),
+    };
+
+    if repo.find_branch(new_name, BranchType::Local).is_ok() {
+        return Err(Error::BranchExists(new_name.to_string()));
+    }
+
+    branch.rename(new_name, false)?;
+
+    let mut config = repo.config()?;
+    for bi in get_branches(repo)? {
+        let key = format!("branch.{}.{}", bi.name, STACKED_PARENT_CONFIG_KEY);
+        if config
+            .get_string(&key)
+            .is_ok_and(|parent| parent == old_name)
+        {
+            config.set_str(&key, new_name)?;
+        }
+    }
+
+    Ok(())
+}
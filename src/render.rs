@@ -0,0 +1,1758 @@
+use git_stacked::config::{HighlightColor, Theme};
+use git_stacked::json::JsonTree;
+use git_stacked::{
+    AheadBehindMap, AliasMap, AuthorMap, BranchCommitsMap, BranchInfo, ChildrenMap, CommitCountMap,
+    CommitLogMap, DiffStatMap, Error, ForkPointMap, MainlineBranches, MergeReadinessMap,
+    ParentOfMap, UpstreamStatus, UpstreamStatusMap, WorktreeBranchMap, detached_roots,
+    get_descendants, is_remote_branch, warn,
+};
+use git2::Oid;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+// Constants for coloring
+const DIM_START: &str = "\x1B[2m";
+const COLOR_RESET: &str = "\x1B[0m";
+// Colors of the successive depths of the tree with --color-depth, wrapping around
+const DEPTH_COLORS: [HighlightColor; 5] = [
+    HighlightColor::Blue,
+    HighlightColor::Green,
+    HighlightColor::Yellow,
+    HighlightColor::Magenta,
+    HighlightColor::Cyan,
+];
+const CURRENT_BRANCH_MARKER: &str = "* ";
+const DIRTY_MARKER: &str = " (dirty)";
+
+// Strings used to draw the ASCII tree.
+pub(crate) struct Glyphs {
+    pub(crate) branch: &'static str,
+    pub(crate) last_branch: &'static str,
+    // Last child when the tree is printed bottom-up
+    pub(crate) first_branch: &'static str,
+    pub(crate) vertical: &'static str,
+    pub(crate) blank: &'static str,
+    pub(crate) arrow: &'static str,
+    pub(crate) ellipsis: &'static str,
+    // Around the header of each stack
+    pub(crate) rule: &'static str,
+    // After the branches ready or not to merge
+    pub(crate) ready: &'static str,
+    pub(crate) blocked: &'static str,
+    // Before the commits listed under a branch
+    pub(crate) commit: &'static str,
+}
+
+pub(crate) const UNICODE_GLYPHS: Glyphs = Glyphs {
+    branch: "├── ",
+    last_branch: "└── ",
+    first_branch: "┌── ",
+    vertical: "│   ",
+    blank: "    ",
+    arrow: " → ",
+    ellipsis: "…",
+    rule: "──",
+    ready: "✓",
+    blocked: "✗",
+    commit: "• ",
+};
+
+// For terminals and logs without box-drawing characters
+pub(crate) const ASCII_GLYPHS: Glyphs = Glyphs {
+    branch: "|-- ",
+    last_branch: "`-- ",
+    first_branch: ",-- ",
+    vertical: "|   ",
+    blank: "    ",
+    arrow: " -> ",
+    ellipsis: "...",
+    rule: "--",
+    ready: "[ready]",
+    blocked: "[blocked]",
+    commit: "- ",
+};
+
+// Information used to decorate branch names and shape the tree when printing it.
+pub(crate) struct RenderOptions<'a> {
+    pub(crate) current_branch: Option<&'a str>,
+    // The working tree of the current branch has uncommitted changes
+    pub(crate) dirty: bool,
+    pub(crate) worktree_branches: &'a WorktreeBranchMap,
+    pub(crate) aliases: &'a AliasMap,
+    // Abbreviated OID of each branch, printed next to its name
+    pub(crate) short_oids: Option<&'a HashMap<String, String>>,
+    pub(crate) ahead_behind: Option<&'a AheadBehindMap>,
+    pub(crate) commit_counts: Option<&'a CommitCountMap>,
+    pub(crate) diff_stats: Option<&'a DiffStatMap>,
+    pub(crate) merge_readiness: Option<&'a MergeReadinessMap>,
+    // Printed under the branches with several children
+    pub(crate) fork_points: Option<&'a ForkPointMap>,
+    // Number of hexadecimal digits of the abbreviated OIDs
+    pub(crate) oid_length: usize,
+    pub(crate) color: bool,
+    pub(crate) theme: Theme,
+    // Marker before the roots of detached stacks and its color
+    pub(crate) detached_label: &'a str,
+    pub(crate) detached_color: HighlightColor,
+    pub(crate) mainline_color: HighlightColor,
+    // Color of the protected branch names, uncolored if unset
+    pub(crate) protected_color: Option<HighlightColor>,
+    // Color each depth of the tree differently, when color is enabled
+    pub(crate) color_depth: bool,
+    pub(crate) mainline: &'a MainlineBranches,
+    // Branches never marked detached, even without a parent
+    pub(crate) protected: &'a HashSet<String>,
+    // Maximum depth of the printed tree, roots are at depth 0
+    pub(crate) max_depth: Option<usize>,
+    // Collapse chains of single children on one line
+    pub(crate) compact: bool,
+    // Tip author of each root, the stacks are grouped by author if set
+    pub(crate) root_authors: Option<&'a AuthorMap>,
+    // Branches whose subtree is replaced by the number of hidden branches
+    pub(crate) collapsed: &'a HashSet<String>,
+    pub(crate) glyphs: &'a Glyphs,
+    // Print the tree bottom-up, children above their parent
+    pub(crate) reverse: bool,
+    // Print a header before each stack when there are several
+    pub(crate) separators: bool,
+    pub(crate) upstream_statuses: Option<&'a UpstreamStatusMap>,
+    pub(crate) commit_logs: Option<&'a CommitLogMap>,
+    // Listed under each branch
+    pub(crate) branch_commits: Option<&'a BranchCommitsMap>,
+    // Maximum number of characters of a commit summary
+    pub(crate) log_width: usize,
+    // Current time in seconds since the Unix epoch, used to print relative commit times
+    pub(crate) now: i64,
+    // Maximum number of visible characters of a line of the tree, longer lines are truncated
+    pub(crate) width: Option<usize>,
+    // Prefix removed from the displayed branch names
+    pub(crate) strip_prefix: Option<&'a str>,
+    // Mark the roots of detached stacks, disabled to only show the structure of the tree
+    pub(crate) mark_detached: bool,
+}
+
+impl RenderOptions<'_> {
+    // Returns the branch name without the elided prefix, prefixed with a marker if it's the
+    // currently checked-out branch and followed by the worktree it's checked out in.
+    // With color, remote branches are dimmed and mainline and protected branches highlighted.
+    fn name(&self, branch_name: &str) -> String {
+        let display_name = self
+            .strip_prefix
+            .and_then(|prefix| branch_name.strip_prefix(prefix))
+            .filter(|stripped| !stripped.is_empty())
+            .unwrap_or(branch_name);
+
+        let styled_name = if self.color && is_remote_branch(branch_name) {
+            format!("{}{}{}", DIM_START, display_name, COLOR_RESET)
+        } else if self.color && self.mainline.contains(branch_name) {
+            format!(
+                "{}{}{}",
+                self.mainline_color.ansi_start(self.theme),
+                display_name,
+                COLOR_RESET
+            )
+        } else if self.color
+            && let Some(protected_color) = self.protected_color
+            && self.protected.contains(branch_name)
+        {
+            format!(
+                "{}{}{}",
+                protected_color.ansi_start(self.theme),
+                display_name,
+                COLOR_RESET
+            )
+        } else {
+            display_name.to_string()
+        };
+
+        let mut name = if self.current_branch == Some(branch_name) {
+            format!("{}{}", CURRENT_BRANCH_MARKER, styled_name)
+        } else {
+            styled_name
+        };
+
+        if let Some(worktree_name) = self.worktree_branches.0.get(branch_name) {
+            name.push_str(&format!(" [{}]", worktree_name));
+        }
+
+        if self.dirty && self.current_branch == Some(branch_name) {
+            name.push_str(DIRTY_MARKER);
+        }
+
+        name
+    }
+
+    // Returns the name of the branch and of its aliases, followed by its annotations if available.
+    fn label(&self, branch_name: &str) -> String {
+        let mut label = self.name(branch_name);
+
+        if let Some(short_oid) = self
+            .short_oids
+            .and_then(|short_oids| short_oids.get(branch_name))
+        {
+            label.push(' ');
+            label.push_str(short_oid);
+        }
+
+        for alias in self.aliases.0.get(branch_name).into_iter().flatten() {
+            label.push_str(" = ");
+            label.push_str(&self.name(alias));
+        }
+
+        if let Some(ready) = self
+            .merge_readiness
+            .and_then(|merge_readiness| merge_readiness.0.get(branch_name))
+        {
+            label.push(' ');
+            label.push_str(if *ready {
+                self.glyphs.ready
+            } else {
+                self.glyphs.blocked
+            });
+        }
+
+        if let Some((ahead, behind)) = self
+            .ahead_behind
+            .and_then(|ahead_behind| ahead_behind.0.get(branch_name))
+        {
+            label.push_str(&format!(" (+{}/-{})", ahead, behind));
+        }
+
+        if let Some(count) = self
+            .commit_counts
+            .and_then(|commit_counts| commit_counts.0.get(branch_name))
+        {
+            let plural = if *count == 1 { "" } else { "s" };
+            label.push_str(&format!(" ({} commit{})", count, plural));
+        }
+
+        if let Some(diff_stat) = self
+            .diff_stats
+            .and_then(|diff_stats| diff_stats.0.get(branch_name))
+        {
+            let plural = if diff_stat.files == 1 { "" } else { "s" };
+            let insertions = format!("+{}", diff_stat.insertions);
+            let deletions = format!("-{}", diff_stat.deletions);
+            let (insertions, deletions) = if self.color {
+                (
+                    format!(
+                        "{}{}{}",
+                        HighlightColor::Green.ansi_start(self.theme),
+                        insertions,
+                        COLOR_RESET
+                    ),
+                    format!(
+                        "{}{}{}",
+                        HighlightColor::Red.ansi_start(self.theme),
+                        deletions,
+                        COLOR_RESET
+                    ),
+                )
+            } else {
+                (insertions, deletions)
+            };
+            label.push_str(&format!(
+                " ({} file{} {} {})",
+                diff_stat.files, plural, insertions, deletions
+            ));
+        }
+
+        match self
+            .upstream_statuses
+            .and_then(|upstream_statuses| upstream_statuses.0.get(branch_name))
+        {
+            Some(UpstreamStatus::Tracking {
+                remote,
+                ahead,
+                behind,
+            }) => label.push_str(&format!(" [{}: ↑{} ↓{}]", remote, ahead, behind)),
+            Some(UpstreamStatus::NoUpstream) => label.push_str(" [no upstream]"),
+            None => {}
+        }
+
+        if let Some(log) = self
+            .commit_logs
+            .and_then(|commit_logs| commit_logs.0.get(branch_name))
+        {
+            label.push_str(&format!(
+                " - {} ({})",
+                sanitize_summary(&log.summary, self.log_width),
+                format_relative_time(self.now - log.time)
+            ));
+        }
+
+        label
+    }
+
+    // Returns the label of branch_name at depth. In compact mode it's followed by the labels of
+    // its descendants as long as each of them is the only child of the previous one.
+    // Also returns the last branch of the line and its depth.
+    fn chain<'b>(
+        &self,
+        branch_name: &'b str,
+        children_map: &'b ChildrenMap,
+        depth: usize,
+    ) -> (String, &'b str, usize) {
+        let mut line = self.label(branch_name);
+        let mut last = branch_name;
+        let mut depth = depth;
+
+        if self.compact {
+            while let Some([child_name]) = children_map.0.get(last).map(Vec::as_slice)
+                && self.max_depth.is_none_or(|max_depth| depth < max_depth)
+                && !self.collapsed.contains(last)
+            {
+                line.push_str(self.glyphs.arrow);
+                line.push_str(&self.label(child_name));
+                last = child_name;
+                depth += 1;
+            }
+        }
+
+        (line, last, depth)
+    }
+
+    // Appends the commits of branch_name to lines, under its line. prefix is the one of its
+    // children, the vertical line going down to them if it has any.
+    fn push_commit_lines(
+        &self,
+        branch_name: &str,
+        children_map: &ChildrenMap,
+        prefix: &str,
+        lines: &mut Vec<String>,
+    ) {
+        let Some(branch_commits) = self
+            .branch_commits
+            .and_then(|branch_commits| branch_commits.0.get(branch_name))
+        else {
+            return;
+        };
+
+        let has_children = children_map
+            .0
+            .get(branch_name)
+            .is_some_and(|children| !children.is_empty());
+        let prefix = format!(
+            "{}{}",
+            prefix,
+            if has_children {
+                self.glyphs.vertical
+            } else {
+                self.glyphs.blank
+            }
+        );
+
+        for commit in &branch_commits.commits {
+            let mut short_oid = commit.oid.to_string();
+            short_oid.truncate(self.oid_length);
+            lines.push(format!(
+                "{}{}{} {}",
+                prefix,
+                self.glyphs.commit,
+                short_oid,
+                sanitize_summary(&commit.summary, self.log_width)
+            ));
+        }
+
+        let hidden = branch_commits.total - branch_commits.commits.len();
+        if hidden > 0 {
+            lines.push(format!(
+                "{}{}{} {} more",
+                prefix, self.glyphs.commit, self.glyphs.ellipsis, hidden
+            ));
+        }
+    }
+
+    // Writes a line of the tree to out, truncated to the width if there's one.
+    fn write_line(&self, out: &mut dyn Write, line: &str) -> std::io::Result<()> {
+        match self.width {
+            Some(width) => writeln!(
+                out,
+                "{}",
+                truncate_visible(line, width, self.glyphs.ellipsis)
+            ),
+            None => writeln!(out, "{}", line),
+        }
+    }
+
+    // Returns line in the color of depth if coloring by depth is enabled.
+    fn depth_colored(&self, line: String, depth: usize) -> String {
+        if self.color && self.color_depth {
+            format!(
+                "{}{}{}",
+                DEPTH_COLORS[depth % DEPTH_COLORS.len()].ansi_start(self.theme),
+                line,
+                COLOR_RESET
+            )
+        } else {
+            line
+        }
+    }
+
+    // Returns the roots of detached stacks, if they are marked.
+    fn detached_roots<'b>(&self, roots: &'b [String], parent_of: &ParentOfMap) -> HashSet<&'b str> {
+        if self.mark_detached {
+            detached_roots(roots, parent_of, self.mainline, self.protected)
+        } else {
+            HashSet::new()
+        }
+    }
+
+    // Returns the label prefixed with the detached marker, highlighted if color is enabled.
+    fn detached(&self, label: &str) -> String {
+        if !self.mark_detached {
+            label.to_string()
+        } else if self.color {
+            format!(
+                "{}{}{} {}",
+                self.detached_color.ansi_start(self.theme),
+                self.detached_label,
+                COLOR_RESET,
+                label
+            )
+        } else {
+            format!("{} {}", self.detached_label, label)
+        }
+    }
+}
+
+// Strips control characters from a commit summary so it can't break the tree layout, and
+// truncates it to width characters.
+fn sanitize_summary(summary: &str, width: usize) -> String {
+    let summary: Vec<char> = summary.chars().filter(|c| !c.is_control()).collect();
+    if summary.len() <= width {
+        return summary.into_iter().collect();
+    }
+
+    let mut truncated: String = summary[..width.saturating_sub(1)].iter().collect();
+    truncated.push('…');
+    truncated
+}
+
+// Truncates line to width visible characters, the last ones being replaced by ellipsis.
+// ANSI escape sequences don't count toward the width and are kept, colors are reset after the
+// ellipsis so a truncated colored part doesn't leak into the next line.
+fn truncate_visible(line: &str, width: usize, ellipsis: &str) -> String {
+    let visible_len = |s: &str| {
+        let mut len = 0;
+        let mut in_escape = false;
+        for c in s.chars() {
+            match (in_escape, c) {
+                (false, '\x1B') => in_escape = true,
+                (false, _) => len += 1,
+                (true, 'm') => in_escape = false,
+                (true, _) => {}
+            }
+        }
+        len
+    };
+
+    if visible_len(line) <= width {
+        return line.to_string();
+    }
+
+    let keep = width.saturating_sub(visible_len(ellipsis));
+    let mut truncated = String::new();
+    let mut len = 0;
+    let mut in_escape = false;
+    let mut has_escapes = false;
+    for c in line.chars() {
+        match (in_escape, c) {
+            (false, '\x1B') => {
+                in_escape = true;
+                has_escapes = true;
+            }
+            (false, _) if len == keep => break,
+            (false, _) => len += 1,
+            (true, 'm') => in_escape = false,
+            (true, _) => {}
+        }
+        truncated.push(c);
+    }
+    truncated.push_str(ellipsis);
+    if has_escapes {
+        truncated.push_str(COLOR_RESET);
+    }
+
+    truncated
+}
+
+// Formats a duration in seconds like git does for relative dates, e.g. "3 days ago".
+fn format_relative_time(seconds: i64) -> String {
+    const UNITS: [(i64, &str); 6] = [
+        (365 * 24 * 60 * 60, "year"),
+        (30 * 24 * 60 * 60, "month"),
+        (7 * 24 * 60 * 60, "week"),
+        (24 * 60 * 60, "day"),
+        (60 * 60, "hour"),
+        (60, "minute"),
+    ];
+
+    if seconds < 0 {
+        return "in the future".to_string();
+    }
+
+    for (unit_seconds, unit_name) in UNITS {
+        let count = seconds / unit_seconds;
+        if count > 0 {
+            let plural = if count > 1 { "s" } else { "" };
+            return format!("{} {}{} ago", count, unit_name, plural);
+        }
+    }
+
+    "just now".to_string()
+}
+
+// Appends the lines of the ASCII tree structure in children_map to lines, recursively.
+// depth is the depth of the children of parent_branch_name.
+//
+// In reverse mode the lines are meant to be printed bottom-up, so the last child, which ends up
+// at the top, is drawn with a downward connector.
+fn push_ascii_tree_lines(
+    parent_branch_name: &str,
+    children_map: &ChildrenMap,
+    current_prefix: &str,
+    depth: usize,
+    options: &RenderOptions,
+    lines: &mut Vec<String>,
+) {
+    let last_connector = if options.reverse {
+        options.glyphs.first_branch
+    } else {
+        options.glyphs.last_branch
+    };
+
+    if let Some(children_names) = children_map.0.get(parent_branch_name) {
+        if options.collapsed.contains(parent_branch_name) {
+            lines.push(format!(
+                "{}{}[+{} hidden]",
+                current_prefix,
+                last_connector,
+                get_descendants(parent_branch_name, children_map).len()
+            ));
+            return;
+        }
+
+        if let Some(fork_point) = options
+            .fork_points
+            .and_then(|fork_points| fork_points.0.get(parent_branch_name))
+        {
+            let mut short_oid = fork_point.oid.to_string();
+            short_oid.truncate(options.oid_length);
+            let diverged: Vec<String> = fork_point
+                .diverged
+                .iter()
+                .map(|(name, ahead)| format!("{} +{}", name, ahead))
+                .collect();
+            lines.push(format!(
+                "{}{}(fork point {}: {})",
+                current_prefix,
+                options.glyphs.vertical,
+                short_oid,
+                diverged.join(", ")
+            ));
+        }
+
+        if options.max_depth.is_some_and(|max_depth| depth > max_depth) {
+            lines.push(format!(
+                "{}{}{} ({} more)",
+                current_prefix,
+                last_connector,
+                options.glyphs.ellipsis,
+                children_names.len()
+            ));
+            return;
+        }
+
+        let num_children = children_names.len();
+        for (i, child_name) in children_names.iter().enumerate() {
+            let is_last_child = i == num_children - 1;
+            let connector = if is_last_child {
+                last_connector
+            } else {
+                options.glyphs.branch
+            };
+            let (line, last_name, last_depth) = options.chain(child_name, children_map, depth);
+            lines.push(format!(
+                "{}{}{}",
+                current_prefix,
+                connector,
+                options.depth_colored(line, depth)
+            ));
+
+            let prefix_for_grandchildren = format!(
+                "{}{}",
+                current_prefix,
+                if is_last_child {
+                    options.glyphs.blank
+                } else {
+                    options.glyphs.vertical
+                }
+            );
+            options.push_commit_lines(last_name, children_map, &prefix_for_grandchildren, lines);
+            push_ascii_tree_lines(
+                last_name,
+                children_map,
+                &prefix_for_grandchildren,
+                last_depth + 1,
+                options,
+                lines,
+            );
+        }
+    }
+}
+
+// Writes the branch tree structure based on the branches, parent-child relationships, and roots
+// to out. Each stack is written and flushed as soon as it's built, so the first ones show up
+// before the whole tree is rendered.
+pub(crate) fn print_tree(
+    out: &mut dyn Write,
+    branches: &[BranchInfo],
+    parent_of: &ParentOfMap,
+    children_map: &ChildrenMap,
+    roots: &[String],
+    options: &RenderOptions,
+) -> Result<(), Error> {
+    let mainline = options.mainline;
+
+    if let Some(prefix) = options.strip_prefix {
+        options.write_line(out, &format!("(prefix {} elided)", prefix))?;
+    }
+
+    if roots.is_empty() {
+        if branches.is_empty() {
+            return Ok(());
+        }
+
+        let mut lines = Vec::new();
+        if !&parent_of.0.is_empty() {
+            // Structure exists but no clear roots (cycles are already rejected by build_children_and_roots)
+            warn(
+                "Could not determine clear root(s) for branch tree. Check for unusual branch structures.",
+            );
+            for bi in branches {
+                // Fallback: print all branches flatly
+                lines.push(options.label(&bi.name));
+            }
+        } else {
+            // No parents found, all branches are effectively roots
+            for bi in branches {
+                let label = options.depth_colored(options.label(&bi.name), 0);
+                let display_name = if mainline.contains(bi.name.as_str())
+                    || options.protected.contains(&bi.name)
+                {
+                    label
+                } else {
+                    options.detached(&label)
+                };
+                lines.push(display_name);
+                // children_map for this branch would be empty or not exist
+                push_ascii_tree_lines(&bi.name, children_map, "", 1, options, &mut lines);
+            }
+        }
+        if options.reverse {
+            lines.reverse();
+        }
+        return write_stack(out, options, None, &lines);
+    }
+
+    let detached_roots = options.detached_roots(roots, parent_of);
+    // Returns the lines of the stack starting at root_branch_name
+    let stack_lines = |root_branch_name: &str| {
+        let (line, last_name, last_depth) = options.chain(root_branch_name, children_map, 0);
+        let line = options.depth_colored(line, 0);
+        let display_name = if detached_roots.contains(root_branch_name) {
+            options.detached(&line)
+        } else {
+            line
+        };
+        let mut lines = vec![display_name];
+        options.push_commit_lines(last_name, children_map, "", &mut lines);
+        push_ascii_tree_lines(
+            last_name,
+            children_map,
+            "",
+            last_depth + 1,
+            options,
+            &mut lines,
+        );
+        if options.reverse {
+            lines.reverse();
+        }
+        lines
+    };
+
+    // Bottom-up output starts with the last stack
+    let mut ordered_roots: Vec<&String> = if options.reverse {
+        roots.iter().rev().collect()
+    } else {
+        roots.iter().collect()
+    };
+    let author = |root_branch_name: &str| {
+        options
+            .root_authors
+            .map(|authors| authors.0.get(root_branch_name).map_or("", String::as_str))
+    };
+    // The sort is stable, the stacks of an author stay in order
+    ordered_roots.sort_by_key(|root_branch_name| author(root_branch_name));
+
+    let separators = options.separators && roots.len() > 1;
+    let mut current_author = None;
+    for (i, root_branch_name) in ordered_roots.into_iter().enumerate() {
+        // Each author starts a group of stacks, with their own header
+        if let Some(author) = author(root_branch_name)
+            && current_author != Some(author)
+        {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            options.write_line(out, &format!("Author: {}", author))?;
+            current_author = Some(author);
+        } else if separators && i > 0 {
+            writeln!(out)?;
+        }
+
+        let header = separators.then_some((i, root_branch_name.as_str()));
+        write_stack(out, options, header, &stack_lines(root_branch_name))?;
+        out.flush()?;
+    }
+
+    Ok(())
+}
+
+// Writes the lines of a stack, preceded by a header with its number and root when given.
+fn write_stack(
+    out: &mut dyn Write,
+    options: &RenderOptions,
+    header: Option<(usize, &str)>,
+    lines: &[String],
+) -> Result<(), Error> {
+    if let Some((i, root_branch_name)) = header {
+        options.write_line(
+            out,
+            &format!(
+                "{} Stack {}: {} {}",
+                options.glyphs.rule,
+                i + 1,
+                root_branch_name,
+                options.glyphs.rule
+            ),
+        )?;
+    }
+    for line in lines {
+        options.write_line(out, line)?;
+    }
+
+    Ok(())
+}
+
+// Prints the branch tree as a nested JSON document.
+pub(crate) fn print_json(
+    branches: &[BranchInfo],
+    parent_of: &ParentOfMap,
+    children_map: &ChildrenMap,
+    roots: &[String],
+    options: &RenderOptions,
+) -> Result<(), Error> {
+    let detached_roots = options.detached_roots(roots, parent_of);
+    let tree = JsonTree::build(
+        branches,
+        children_map,
+        roots,
+        &detached_roots,
+        options.aliases,
+    );
+
+    println!("{}", serde_json::to_string_pretty(&tree)?);
+
+    Ok(())
+}
+
+// A line of the JSON Lines output.
+#[derive(Debug, Serialize)]
+struct JsonlBranch<'a> {
+    name: &'a str,
+    oid: String,
+    parent: Option<&'a str>,
+}
+
+// Prints one JSON object per branch, in branch name order, with its detected parent or null.
+// Aliases follow the branch they stand for, with the same parent.
+pub(crate) fn print_jsonl(
+    branches: &[BranchInfo],
+    parent_of: &ParentOfMap,
+    options: &RenderOptions,
+) -> Result<(), Error> {
+    for bi in branches {
+        let parent = parent_of.0.get(&bi.name).map(String::as_str);
+        let aliases = options.aliases.0.get(&bi.name).into_iter().flatten();
+
+        for name in std::iter::once(&bi.name).chain(aliases) {
+            let line = JsonlBranch {
+                name,
+                oid: bi.oid.to_string(),
+                parent,
+            };
+            println!("{}", serde_json::to_string(&line)?);
+        }
+    }
+
+    Ok(())
+}
+
+// Appends the names of branch_name, its aliases and its descendants to lines, indented by two
+// spaces per depth level.
+fn push_list_lines(
+    branch_name: &str,
+    children_map: &ChildrenMap,
+    depth: usize,
+    options: &RenderOptions,
+    lines: &mut Vec<String>,
+) {
+    let indent = "  ".repeat(depth);
+    let aliases = options.aliases.0.get(branch_name).into_iter().flatten();
+    for name in std::iter::once(branch_name).chain(aliases.map(String::as_str)) {
+        lines.push(format!("{}{}", indent, name));
+    }
+
+    for child_name in children_map.0.get(branch_name).into_iter().flatten() {
+        push_list_lines(child_name, children_map, depth + 1, options, lines);
+    }
+}
+
+// Writes the branch names to out, roots first and each branch indented below its parent.
+pub(crate) fn print_list(
+    out: &mut dyn Write,
+    children_map: &ChildrenMap,
+    roots: &[String],
+    options: &RenderOptions,
+) -> Result<(), Error> {
+    let mut lines = Vec::new();
+    for root_branch_name in roots {
+        push_list_lines(root_branch_name, children_map, 0, options, &mut lines);
+    }
+
+    for line in lines {
+        writeln!(out, "{}", line)?;
+    }
+
+    Ok(())
+}
+
+// Quotes a branch name as a DOT identifier.
+fn dot_quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Prints the branch tree as a Graphviz digraph with an edge from each parent to its children.
+pub(crate) fn print_dot(
+    branches: &[BranchInfo],
+    parent_of: &ParentOfMap,
+    children_map: &ChildrenMap,
+    roots: &[String],
+    options: &RenderOptions,
+) -> Result<(), Error> {
+    let mainline = options.mainline;
+    let detached_roots = options.detached_roots(roots, parent_of);
+    let mainline_color = options.mainline_color.css_name();
+    let detached_color = options.detached_color.css_name();
+
+    println!("digraph stacked {{");
+
+    for bi in branches {
+        let name = bi.name.as_str();
+
+        let mut attributes = Vec::new();
+        if mainline.contains(name) {
+            attributes.push(format!("color={}", mainline_color));
+            attributes.push(format!("fontcolor={}", mainline_color));
+        } else if detached_roots.contains(name) {
+            attributes.push(format!("color={}", detached_color));
+            attributes.push(format!("fontcolor={}", detached_color));
+            attributes.push("style=bold".to_string());
+        }
+        if let Some(aliases) = options.aliases.0.get(name) {
+            let label = std::iter::once(name)
+                .chain(aliases.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(" = ");
+            attributes.push(format!("label={}", dot_quote(&label)));
+        }
+
+        if attributes.is_empty() {
+            println!("    {};", dot_quote(name));
+        } else {
+            println!("    {} [{}];", dot_quote(name), attributes.join(", "));
+        }
+    }
+
+    for (parent_name, children_names) in &children_map.0 {
+        for child_name in children_names {
+            println!(
+                "    {} -> {};",
+                dot_quote(parent_name),
+                dot_quote(child_name)
+            );
+        }
+    }
+
+    println!("}}");
+
+    Ok(())
+}
+
+// Quotes a branch name for use as a Mermaid node label.
+fn mermaid_label(name: &str) -> String {
+    format!("[\"{}\"]", name.replace('"', "#quot;"))
+}
+
+// Prints the branch tree as a Mermaid flowchart with an edge from each parent to its children.
+//
+// Branch names can't be used as node ids, so nodes are named after their position in branches.
+pub(crate) fn print_mermaid(
+    branches: &[BranchInfo],
+    parent_of: &ParentOfMap,
+    children_map: &ChildrenMap,
+    roots: &[String],
+    options: &RenderOptions,
+) -> Result<(), Error> {
+    let mainline = options.mainline;
+    let detached_roots = options.detached_roots(roots, parent_of);
+
+    let node_ids: HashMap<&str, String> = branches
+        .iter()
+        .enumerate()
+        .map(|(i, bi)| (bi.name.as_str(), format!("n{}", i)))
+        .collect();
+
+    let mainline_color = options.mainline_color.css_name();
+    let detached_color = options.detached_color.css_name();
+
+    println!("graph TD");
+    println!(
+        "    classDef mainline color:{},stroke:{};",
+        mainline_color, mainline_color
+    );
+    println!(
+        "    classDef detached color:{},stroke:{},stroke-width:2px;",
+        detached_color, detached_color
+    );
+
+    for bi in branches {
+        let name = bi.name.as_str();
+        let class = if mainline.contains(name) {
+            ":::mainline"
+        } else if detached_roots.contains(name) {
+            ":::detached"
+        } else {
+            ""
+        };
+        let label = std::iter::once(name)
+            .chain(
+                options
+                    .aliases
+                    .0
+                    .get(name)
+                    .into_iter()
+                    .flatten()
+                    .map(String::as_str),
+            )
+            .collect::<Vec<_>>()
+            .join(" = ");
+        println!("    {}{}{}", node_ids[name], mermaid_label(&label), class);
+    }
+
+    for (parent_name, children_names) in &children_map.0 {
+        for child_name in children_names {
+            if let (Some(parent_id), Some(child_id)) = (
+                node_ids.get(parent_name.as_str()),
+                node_ids.get(child_name.as_str()),
+            ) {
+                println!("    {} --> {}", parent_id, child_id);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Prints the chain of branches from the root of the stack of the current branch down to it on a
+// single line, the current branch being last.
+pub(crate) fn print_head_path(path: &[&str], glyphs: &Glyphs) {
+    println!("{}*", path.join(glyphs.arrow));
+}
+
+// Returns the depth of the deepest descendant of branch_name, branch_name being at depth.
+fn max_depth(branch_name: &str, children_map: &ChildrenMap, depth: usize) -> usize {
+    children_map
+        .0
+        .get(branch_name)
+        .into_iter()
+        .flatten()
+        .map(|child_name| max_depth(child_name, children_map, depth + 1))
+        .max()
+        .unwrap_or(depth)
+}
+
+// Returns the number of branches in the tree, branches shown as aliases of another one included.
+fn branch_count(branches: &[BranchInfo], aliases: &AliasMap) -> usize {
+    branches.len() + aliases.0.values().map(Vec::len).sum::<usize>()
+}
+
+// Prints a one-line summary of the tree to stderr: number of branches, of stacks, depth of the
+// deepest branch and number of detached roots.
+pub(crate) fn print_stats(
+    branches: &[BranchInfo],
+    aliases: &AliasMap,
+    parent_of: &ParentOfMap,
+    children_map: &ChildrenMap,
+    roots: &[String],
+    mainline: &MainlineBranches,
+    protected: &HashSet<String>,
+) {
+    let depth = roots
+        .iter()
+        .map(|root| max_depth(root, children_map, 0))
+        .max()
+        .unwrap_or(0);
+    let detached = detached_roots(roots, parent_of, mainline, protected).len();
+    let branch_count = branch_count(branches, aliases);
+
+    eprintln!(
+        "{} branch{}, {} stack{}, max depth {}, {} detached",
+        branch_count,
+        if branch_count == 1 { "" } else { "es" },
+        roots.len(),
+        if roots.len() == 1 { "" } else { "s" },
+        depth,
+        detached
+    );
+}
+
+// Layout of the SVG output, in pixels
+const SVG_MARGIN: usize = 16;
+const SVG_INDENT: usize = 24;
+const SVG_ROW_HEIGHT: usize = 24;
+const SVG_CHAR_WIDTH: usize = 8;
+const SVG_NODE_RADIUS: usize = 4;
+
+// Escapes a string for use as XML text or attribute value.
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// A branch placed in the SVG output: its depth gives its column, and its position in
+// depth-first order its row.
+struct SvgNode<'b> {
+    name: &'b str,
+    parent_row: Option<usize>,
+    depth: usize,
+}
+
+// Appends branch_name and its descendants to nodes, depth first.
+fn push_svg_nodes<'b>(
+    branch_name: &'b str,
+    children_map: &'b ChildrenMap,
+    parent_row: Option<usize>,
+    depth: usize,
+    nodes: &mut Vec<SvgNode<'b>>,
+) {
+    let row = nodes.len();
+    nodes.push(SvgNode {
+        name: branch_name,
+        parent_row,
+        depth,
+    });
+
+    for child_name in children_map.0.get(branch_name).into_iter().flatten() {
+        push_svg_nodes(child_name, children_map, Some(row), depth + 1, nodes);
+    }
+}
+
+// Totals printed by --count-only.
+#[derive(Debug, Serialize)]
+struct Counts {
+    branches: usize,
+    stacks: usize,
+    max_depth: usize,
+}
+
+// Prints the number of branches, aliases included, of stacks and the depth of the deepest branch
+// on one line, as a JSON object if json is set.
+pub(crate) fn print_counts(
+    branches: &[BranchInfo],
+    aliases: &AliasMap,
+    children_map: &ChildrenMap,
+    roots: &[String],
+    json: bool,
+) -> Result<(), Error> {
+    let counts = Counts {
+        branches: branch_count(branches, aliases),
+        stacks: roots.len(),
+        max_depth: roots
+            .iter()
+            .map(|root| max_depth(root, children_map, 0))
+            .max()
+            .unwrap_or(0),
+    };
+
+    if json {
+        println!("{}", serde_json::to_string(&counts)?);
+    } else {
+        println!(
+            "branches={} stacks={} max_depth={}",
+            counts.branches, counts.stacks, counts.max_depth
+        );
+    }
+
+    Ok(())
+}
+
+// Prints the branch tree as a standalone SVG document, with a row per branch indented by its
+// depth and an elbow line from each parent to its children.
+pub(crate) fn print_svg(
+    parent_of: &ParentOfMap,
+    children_map: &ChildrenMap,
+    roots: &[String],
+    options: &RenderOptions,
+) -> Result<(), Error> {
+    let mainline = options.mainline;
+    let detached_roots = options.detached_roots(roots, parent_of);
+
+    let mut nodes = Vec::new();
+    for root_branch_name in roots {
+        push_svg_nodes(root_branch_name, children_map, None, 0, &mut nodes);
+    }
+
+    let labels: Vec<String> = nodes
+        .iter()
+        .map(|node| {
+            std::iter::once(node.name)
+                .chain(
+                    options
+                        .aliases
+                        .0
+                        .get(node.name)
+                        .into_iter()
+                        .flatten()
+                        .map(String::as_str),
+                )
+                .collect::<Vec<_>>()
+                .join(" = ")
+        })
+        .collect();
+
+    let x = |depth: usize| SVG_MARGIN + depth * SVG_INDENT;
+    let y = |row: usize| SVG_MARGIN + row * SVG_ROW_HEIGHT;
+
+    let width = nodes
+        .iter()
+        .zip(&labels)
+        .map(|(node, label)| {
+            x(node.depth) + 2 * SVG_NODE_RADIUS + label.chars().count() * SVG_CHAR_WIDTH
+        })
+        .max()
+        .unwrap_or(0)
+        + SVG_MARGIN;
+    let height = y(nodes.len().saturating_sub(1)) + SVG_MARGIN;
+
+    println!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"monospace\" font-size=\"13\">",
+        width, height
+    );
+
+    for (row, node) in nodes.iter().enumerate() {
+        if let Some(parent_row) = node.parent_row {
+            println!(
+                "  <path d=\"M{} {} V{} H{}\" fill=\"none\" stroke=\"gray\"/>",
+                x(node.depth - 1),
+                y(parent_row),
+                y(row),
+                x(node.depth)
+            );
+        }
+    }
+
+    for ((row, node), label) in nodes.iter().enumerate().zip(&labels) {
+        let color = if mainline.contains(node.name) {
+            options.mainline_color.css_name()
+        } else if detached_roots.contains(node.name) {
+            options.detached_color.css_name()
+        } else {
+            "black"
+        };
+
+        println!(
+            "  <circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"/>",
+            x(node.depth),
+            y(row),
+            SVG_NODE_RADIUS,
+            color
+        );
+        println!(
+            "  <text x=\"{}\" y=\"{}\" dominant-baseline=\"middle\" fill=\"{}\">{}</text>",
+            x(node.depth) + 2 * SVG_NODE_RADIUS,
+            y(row),
+            color,
+            xml_escape(label)
+        );
+    }
+
+    println!("</svg>");
+
+    Ok(())
+}
+
+// Appends the rows of branch_name, its aliases and its descendants to rows, depth first.
+fn push_tsv_rows(
+    branch_name: &str,
+    children_map: &ChildrenMap,
+    depth: usize,
+    options: &RenderOptions,
+    rows: &mut Vec<(String, usize)>,
+) {
+    let aliases = options.aliases.0.get(branch_name).into_iter().flatten();
+    for name in std::iter::once(branch_name).chain(aliases.map(String::as_str)) {
+        rows.push((name.to_string(), depth));
+    }
+
+    for child_name in children_map.0.get(branch_name).into_iter().flatten() {
+        push_tsv_rows(child_name, children_map, depth + 1, options, rows);
+    }
+}
+
+// Prints a header then one tab-separated row per branch, in tree order: name, parent or empty,
+// OID, depth and whether it's a mainline branch.
+pub(crate) fn print_tsv(
+    branches: &[BranchInfo],
+    parent_of: &ParentOfMap,
+    children_map: &ChildrenMap,
+    roots: &[String],
+    options: &RenderOptions,
+) -> Result<(), Error> {
+    let mut oids: HashMap<&str, Oid> = HashMap::new();
+    for bi in branches {
+        oids.insert(&bi.name, bi.oid);
+        for alias in options.aliases.0.get(&bi.name).into_iter().flatten() {
+            oids.insert(alias, bi.oid);
+        }
+    }
+
+    let mut rows = Vec::new();
+    for root_branch_name in roots {
+        push_tsv_rows(root_branch_name, children_map, 0, options, &mut rows);
+    }
+
+    println!("name\tparent\toid\tdepth\tmainline");
+    for (name, depth) in rows {
+        // Aliases have the parent of the branch they stand for
+        let parent = parent_of
+            .0
+            .get(options.aliases.representative(&name))
+            .map_or("", String::as_str);
+        let oid = oids
+            .get(name.as_str())
+            .map(Oid::to_string)
+            .unwrap_or_default();
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            name,
+            parent,
+            oid,
+            depth,
+            options.mainline.contains(&name)
+        );
+    }
+
+    Ok(())
+}
+
+// Prints one line per branch in tree order, with tab-separated fields: name, parent or empty,
+// depth and comma-separated flags among current, mainline, detached and remote. Git forbids
+// control characters in branch names, so fields never contain tabs or newlines.
+//
+// This format only ever changes by adding flags or fields at the end of the lines.
+pub(crate) fn print_porcelain(
+    parent_of: &ParentOfMap,
+    children_map: &ChildrenMap,
+    roots: &[String],
+    options: &RenderOptions,
+) -> Result<(), Error> {
+    let detached_roots = options.detached_roots(roots, parent_of);
+
+    let mut rows = Vec::new();
+    for root_branch_name in roots {
+        push_tsv_rows(root_branch_name, children_map, 0, options, &mut rows);
+    }
+
+    for (name, depth) in rows {
+        let representative = options.aliases.representative(&name);
+        let parent = parent_of.0.get(representative).map_or("", String::as_str);
+
+        let mut flags = Vec::new();
+        if options.current_branch == Some(name.as_str()) {
+            flags.push("current");
+        }
+        if options.mainline.contains(&name) {
+            flags.push("mainline");
+        }
+        if detached_roots.contains(representative) {
+            flags.push("detached");
+        }
+        if is_remote_branch(&name) {
+            flags.push("remote");
+        }
+
+        println!("{}\t{}\t{}\t{}", name, parent, depth, flags.join(","));
+    }
+
+    Ok(())
+}
+
+// Style and script embedded in the HTML page, clicking a branch with children folds its subtree.
+const HTML_STYLE: &str = "ul { list-style: none; padding-left: 1.5em; }
+    li > span { cursor: default; }
+    li.parent > span { cursor: pointer; }
+    li.parent > span::before { content: \"\\25BE  \"; }
+    li.parent.collapsed > span::before { content: \"\\25B8  \"; }
+    li.collapsed > ul { display: none; }";
+const HTML_SCRIPT: &str = "document.querySelectorAll(\"li.parent > span\").forEach((span) => {
+      span.addEventListener(\"click\", () => span.parentElement.classList.toggle(\"collapsed\"));
+    });";
+
+// Appends the list item of branch_name and the nested list of its descendants to lines,
+// indented by level.
+fn push_html_lines(
+    branch_name: &str,
+    children_map: &ChildrenMap,
+    detached_roots: &HashSet<&str>,
+    level: usize,
+    options: &RenderOptions,
+    lines: &mut Vec<String>,
+) {
+    let indent = "  ".repeat(level + 2);
+    let children = children_map.0.get(branch_name).filter(|c| !c.is_empty());
+
+    let mut classes = Vec::new();
+    if options.mainline.contains(branch_name) {
+        classes.push("mainline");
+    } else if detached_roots.contains(branch_name) {
+        classes.push("detached");
+    }
+    if children.is_some() {
+        classes.push("parent");
+    }
+    let class = if classes.is_empty() {
+        String::new()
+    } else {
+        format!(" class=\"{}\"", classes.join(" "))
+    };
+
+    let label = std::iter::once(branch_name)
+        .chain(
+            options
+                .aliases
+                .0
+                .get(branch_name)
+                .into_iter()
+                .flatten()
+                .map(String::as_str),
+        )
+        .collect::<Vec<_>>()
+        .join(" = ");
+    let label = xml_escape(&label);
+
+    match children {
+        Some(children) => {
+            lines.push(format!("{}<li{}><span>{}</span>", indent, class, label));
+            lines.push(format!("{}  <ul>", indent));
+            for child_name in children {
+                push_html_lines(
+                    child_name,
+                    children_map,
+                    detached_roots,
+                    level + 2,
+                    options,
+                    lines,
+                );
+            }
+            lines.push(format!("{}  </ul>", indent));
+            lines.push(format!("{}</li>", indent));
+        }
+        None => lines.push(format!(
+            "{}<li{}><span>{}</span></li>",
+            indent, class, label
+        )),
+    }
+}
+
+// Prints the branch tree as a self-contained HTML page with a collapsible nested list, mainline
+// branches and detached roots colored like in the terminal.
+pub(crate) fn print_html(
+    parent_of: &ParentOfMap,
+    children_map: &ChildrenMap,
+    roots: &[String],
+    options: &RenderOptions,
+) -> Result<(), Error> {
+    let detached_roots = options.detached_roots(roots, parent_of);
+
+    let mut lines = Vec::new();
+    for root_branch_name in roots {
+        push_html_lines(
+            root_branch_name,
+            children_map,
+            &detached_roots,
+            0,
+            options,
+            &mut lines,
+        );
+    }
+
+    println!("<!DOCTYPE html>");
+    println!("<html>");
+    println!("<head>");
+    println!("  <meta charset=\"utf-8\">");
+    println!("  <title>git stacked</title>");
+    println!("  <style>");
+    println!("    body {{ font-family: monospace; }}");
+    println!("    {}", HTML_STYLE);
+    println!(
+        "    .mainline > span {{ color: {}; }}",
+        options.mainline_color.css_name()
+    );
+    println!(
+        "    .detached > span {{ color: {}; font-weight: bold; }}",
+        options.detached_color.css_name()
+    );
+    println!("  </style>");
+    println!("</head>");
+    println!("<body>");
+    println!("  <ul>");
+    for line in lines {
+        println!("{}", line);
+    }
+    println!("  </ul>");
+    println!("  <script>");
+    println!("    {}", HTML_SCRIPT);
+    println!("  </script>");
+    println!("</body>");
+    println!("</html>");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git_stacked::build_children_and_roots;
+
+    // The tree rendered by the tests and the data borrowed by their render options.
+    struct Fixture {
+        branches: Vec<BranchInfo>,
+        parent_of: ParentOfMap,
+        children_map: ChildrenMap,
+        roots: Vec<String>,
+        worktree_branches: WorktreeBranchMap,
+        aliases: AliasMap,
+        mainline: MainlineBranches,
+        collapsed: HashSet<String>,
+        protected: HashSet<String>,
+    }
+
+    impl Fixture {
+        fn new(names: &[&str], parents: &[(&str, &str)]) -> Self {
+            let branches: Vec<BranchInfo> = names
+                .iter()
+                .map(|name| BranchInfo {
+                    name: name.to_string(),
+                    oid: git2::Oid::zero(),
+                })
+                .collect();
+            let parent_of = ParentOfMap(
+                parents
+                    .iter()
+                    .map(|(child, parent)| (child.to_string(), parent.to_string()))
+                    .collect(),
+            );
+            let stack = build_children_and_roots(&branches, &parent_of).unwrap();
+
+            Fixture {
+                branches,
+                parent_of,
+                children_map: stack.children_map,
+                roots: stack.roots,
+                worktree_branches: WorktreeBranchMap(HashMap::new()),
+                aliases: AliasMap(HashMap::new()),
+                mainline: MainlineBranches::default(),
+                collapsed: HashSet::new(),
+                protected: HashSet::new(),
+            }
+        }
+
+        // main -> a -> b -> c and main -> d, next to the detached stack other -> e.
+        fn two_stacks() -> Self {
+            Fixture::new(
+                &["a", "b", "c", "d", "e", "main", "other"],
+                &[
+                    ("a", "main"),
+                    ("b", "a"),
+                    ("c", "b"),
+                    ("d", "main"),
+                    ("e", "other"),
+                ],
+            )
+        }
+
+        fn options(&self) -> RenderOptions<'_> {
+            RenderOptions {
+                current_branch: None,
+                dirty: false,
+                worktree_branches: &self.worktree_branches,
+                aliases: &self.aliases,
+                short_oids: None,
+                ahead_behind: None,
+                commit_counts: None,
+                diff_stats: None,
+                merge_readiness: None,
+                fork_points: None,
+                oid_length: 7,
+                color: false,
+                theme: Theme::Dark,
+                detached_label: "(detached)",
+                detached_color: HighlightColor::Red,
+                mainline_color: HighlightColor::Green,
+                protected_color: None,
+                color_depth: false,
+                mainline: &self.mainline,
+                protected: &self.protected,
+                max_depth: None,
+                compact: false,
+                root_authors: None,
+                collapsed: &self.collapsed,
+                glyphs: &UNICODE_GLYPHS,
+                reverse: false,
+                separators: true,
+                upstream_statuses: None,
+                commit_logs: None,
+                branch_commits: None,
+                log_width: 50,
+                now: 0,
+                width: None,
+                strip_prefix: None,
+                mark_detached: true,
+            }
+        }
+
+        fn render(&self, options: &RenderOptions) -> String {
+            self.render_roots(&self.roots, options)
+        }
+
+        fn render_roots(&self, roots: &[String], options: &RenderOptions) -> String {
+            let mut out = Vec::new();
+            print_tree(
+                &mut out,
+                &self.branches,
+                &self.parent_of,
+                &self.children_map,
+                roots,
+                options,
+            )
+            .unwrap();
+            String::from_utf8(out).unwrap()
+        }
+    }
+
+    #[test]
+    fn stacks_are_separated() {
+        let fixture = Fixture::two_stacks();
+
+        assert_eq!(
+            fixture.render(&fixture.options()),
+            "\
+── Stack 1: main ──
+main
+├── a
+│   └── b
+│       └── c
+└── d
+
+── Stack 2: other ──
+(detached) other
+└── e
+"
+        );
+    }
+
+    #[test]
+    fn separators_can_be_disabled() {
+        let fixture = Fixture::two_stacks();
+        let options = RenderOptions {
+            separators: false,
+            ..fixture.options()
+        };
+
+        assert_eq!(
+            fixture.render(&options),
+            "\
+main
+├── a
+│   └── b
+│       └── c
+└── d
+(detached) other
+└── e
+"
+        );
+    }
+
+    #[test]
+    fn single_stack_has_no_separator() {
+        let fixture = Fixture::new(&["a", "main"], &[("a", "main")]);
+
+        assert_eq!(fixture.render(&fixture.options()), "main\n└── a\n");
+    }
+
+    #[test]
+    fn ascii_glyphs() {
+        let fixture = Fixture::two_stacks();
+        let options = RenderOptions {
+            glyphs: &ASCII_GLYPHS,
+            ..fixture.options()
+        };
+
+        assert_eq!(
+            fixture.render(&options),
+            "\
+-- Stack 1: main --
+main
+|-- a
+|   `-- b
+|       `-- c
+`-- d
+
+-- Stack 2: other --
+(detached) other
+`-- e
+"
+        );
+    }
+
+    #[test]
+    fn depth_is_truncated() {
+        let fixture = Fixture::two_stacks();
+        let options = RenderOptions {
+            max_depth: Some(1),
+            separators: false,
+            ..fixture.options()
+        };
+
+        assert_eq!(
+            fixture.render(&options),
+            "\
+main
+├── a
+│   └── … (1 more)
+└── d
+(detached) other
+└── e
+"
+        );
+    }
+
+    #[test]
+    fn compact_collapses_single_children() {
+        let fixture = Fixture::two_stacks();
+        let options = RenderOptions {
+            compact: true,
+            separators: false,
+            ..fixture.options()
+        };
+
+        assert_eq!(
+            fixture.render(&options),
+            "\
+main
+├── a → b → c
+└── d
+(detached) other → e
+"
+        );
+    }
+
+    #[test]
+    fn reverse_prints_bottom_up() {
+        let fixture = Fixture::two_stacks();
+        let options = RenderOptions {
+            reverse: true,
+            ..fixture.options()
+        };
+
+        assert_eq!(
+            fixture.render(&options),
+            "\
+── Stack 1: other ──
+┌── e
+(detached) other
+
+── Stack 2: main ──
+┌── d
+│       ┌── c
+│   ┌── b
+├── a
+main
+"
+        );
+    }
+
+    #[test]
+    fn lines_are_truncated_to_width() {
+        let fixture = Fixture::new(
+            &["a-long-branch-name", "main"],
+            &[("a-long-branch-name", "main")],
+        );
+        let options = RenderOptions {
+            width: Some(10),
+            ..fixture.options()
+        };
+
+        assert_eq!(fixture.render(&options), "main\n└── a-lon…\n");
+    }
+
+    #[test]
+    fn truncate_visible_ignores_ansi_escapes() {
+        assert_eq!(truncate_visible("short", 10, "…"), "short");
+        assert_eq!(truncate_visible("exactly10!", 10, "…"), "exactly10!");
+        assert_eq!(truncate_visible("a long line", 6, "..."), "a l...");
+
+        let colored = format!("{}main{} branch", DIM_START, COLOR_RESET);
+        assert_eq!(truncate_visible(&colored, 11, "…"), colored);
+        assert_eq!(
+            truncate_visible(&colored, 6, "…"),
+            format!("{}main{} …{}", DIM_START, COLOR_RESET, COLOR_RESET)
+        );
+    }
+
+    #[test]
+    fn missing_roots_print_every_branch_flat() {
+        // Without parents every branch stands alone, only the mainline isn't detached
+        let fixture = Fixture::new(&["a", "main"], &[]);
+        assert_eq!(
+            fixture.render_roots(&[], &fixture.options()),
+            "(detached) a\nmain\n"
+        );
+
+        // With parents but no root, the branches are listed without any structure
+        let fixture = Fixture::two_stacks();
+        assert_eq!(
+            fixture.render_roots(&[], &fixture.options()),
+            "a\nb\nc\nd\ne\nmain\nother\n"
+        );
+
+        let fixture = Fixture::new(&[], &[]);
+        assert_eq!(fixture.render_roots(&[], &fixture.options()), "");
+    }
+}
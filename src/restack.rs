@@ -0,0 +1,187 @@
+use crate::{
+    Error, MergeBaseCache, build_children_and_roots, get_branches, get_current_branch,
+    get_descendants, get_parent_of_relationships, has_uncommitted_changes,
+};
+use git2::{BranchType, Oid, RebaseOptions, Repository, build::CheckoutBuilder};
+use std::collections::HashMap;
+
+// A branch moved by restack.
+#[derive(Debug, Clone)]
+pub struct RestackedBranch {
+    pub name: String,
+    pub old_oid: Oid,
+    pub new_oid: Oid,
+    // Number of commits replayed on top of the new parent
+    pub commits: usize,
+}
+
+// Fails if the working tree or the index has changes, untracked files are fine.
+pub fn ensure_clean_working_tree(repo: &Repository) -> Result<(), Error> {
+    if has_uncommitted_changes(repo)? {
+        Err(Error::DirtyWorkingTree)
+    } else {
+        Ok(())
+    }
+}
+
+// Replays the commits of branch_name between upstream_oid and its tip on top of onto_oid,
+// without touching the working tree or any reference. Returns the new tip and the number of
+// commits replayed.
+pub(crate) fn rebase_in_memory(
+    repo: &Repository,
+    branch_name: &str,
+    branch_oid: Oid,
+    upstream_oid: Oid,
+    onto_oid: Oid,
+) -> Result<(Oid, usize), Error> {
+    let branch = repo.find_annotated_commit(branch_oid)?;
+    let upstream = repo.find_annotated_commit(upstream_oid)?;
+    let onto = repo.find_annotated_commit(onto_oid)?;
+
+    let mut rebase_options = RebaseOptions::new();
+    rebase_options.inmemory(true);
+
+    let mut rebase = repo.rebase(
+        Some(&branch),
+        Some(&upstream),
+        Some(&onto),
+        Some(&mut rebase_options),
+    )?;
+    let committer = repo.signature()?;
+
+    let mut new_oid = onto_oid;
+    let mut commits = 0;
+
+    while let Some(operation) = rebase.next() {
+        let operation = operation?;
+
+        if rebase.inmemory_index()?.has_conflicts() {
+            rebase.abort()?;
+            return Err(Error::RebaseConflict(
+                branch_name.to_string(),
+                operation.id().to_string(),
+            ));
+        }
+
+        new_oid = match rebase.commit(None, &committer, None) {
+            Ok(oid) => oid,
+            // The commit is already applied on the new parent, nothing to replay
+            Err(e) if e.code() == git2::ErrorCode::Applied => continue,
+            Err(e) => return Err(Error::Git2(e)),
+        };
+        commits += 1;
+    }
+    rebase.finish(None)?;
+
+    Ok((new_oid, commits))
+}
+
+// Rebases branch_name onto new_parent_name, and its descendants onto their rebased parents if
+// with_descendants is set.
+//
+// The commits replayed are the ones between the current parent of the branch, as detected by
+// get_parent_of_relationships, and its tip. All rebases happen in memory first: if any of them
+// conflicts nothing is changed, otherwise every branch reference is updated at the end.
+pub fn restack(
+    repo: &Repository,
+    branch_name: &str,
+    new_parent_name: &str,
+    with_descendants: bool,
+) -> Result<Vec<RestackedBranch>, Error> {
+    ensure_clean_working_tree(repo)?;
+
+    let branches = get_branches(repo)?;
+    let oids: HashMap<&str, Oid> = branches
+        .iter()
+        .map(|bi| (bi.name.as_str(), bi.oid))
+        .collect();
+
+    let branch_oid = *oids
+        .get(branch_name)
+        .ok_or_else(|| Error::BranchNotFound(branch_name.to_string()))?;
+    let new_parent_oid = *oids
+        .get(new_parent_name)
+        .ok_or_else(|| Error::BranchNotFound(new_parent_name.to_string()))?;
+
+    let parent_of = get_parent_of_relationships(repo, &branches)?;
+    let stack = build_children_and_roots(&branches, &parent_of)?;
+    let descendants = get_descendants(branch_name, &stack.children_map);
+
+    if branch_name == new_parent_name || descendants.iter().any(|d| d == new_parent_name) {
+        return Err(Error::InvalidRestack(
+            branch_name.to_string(),
+            new_parent_name.to_string(),
+        ));
+    }
+
+    // Without a detected parent, replay everything since the fork point with the new parent
+    let upstream_oid = match parent_of.0.get(branch_name) {
+        Some(parent_name) => oids[parent_name.as_str()],
+        None => MergeBaseCache::new(repo)
+            .merge_base(branch_oid, new_parent_oid)?
+            .ok_or_else(|| {
+                Error::NoCommonHistory(branch_name.to_string(), new_parent_name.to_string())
+            })?,
+    };
+
+    let mut restacked = Vec::new();
+    let (new_oid, commits) =
+        rebase_in_memory(repo, branch_name, branch_oid, upstream_oid, new_parent_oid)?;
+    restacked.push(RestackedBranch {
+        name: branch_name.to_string(),
+        old_oid: branch_oid,
+        new_oid,
+        commits,
+    });
+
+    if with_descendants {
+        // Descendants come depth first, so each parent is always rebased before its children
+        let mut new_oids: HashMap<&str, Oid> = HashMap::new();
+        new_oids.insert(branch_name, new_oid);
+
+        for descendant in &descendants {
+            let parent_name = parent_of.0[descendant].as_str();
+            let descendant_oid = oids[descendant.as_str()];
+            let (new_oid, commits) = rebase_in_memory(
+                repo,
+                descendant,
+                descendant_oid,
+                oids[parent_name],
+                new_oids[parent_name],
+            )?;
+            new_oids.insert(descendant, new_oid);
+            restacked.push(RestackedBranch {
+                name: descendant.clone(),
+                old_oid: descendant_oid,
+                new_oid,
+                commits,
+            });
+        }
+    }
+
+    update_branches(repo, &restacked)?;
+
+    Ok(restacked)
+}
+
+// Points every restacked branch at its new tip, and updates the working tree if the
+// current branch moved.
+pub(crate) fn update_branches(
+    repo: &Repository,
+    restacked: &[RestackedBranch],
+) -> Result<(), Error> {
+    let current_branch = get_current_branch(repo)?;
+
+    for rb in restacked {
+        let mut branch = repo.find_branch(&rb.name, BranchType::Local)?;
+        branch
+            .get_mut()
+            .set_target(rb.new_oid, "git-stacked: restack")?;
+
+        if current_branch.as_deref() == Some(rb.name.as_str()) {
+            repo.checkout_head(Some(CheckoutBuilder::new().force()))?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,208 @@
+use crate::restack::{
+    RestackedBranch, ensure_clean_working_tree, rebase_in_memory, update_branches,
+};
+use crate::{
+    Error, MainlineBranches, MergeBaseCache, build_children_and_roots, detach_mainline,
+    get_branches, get_descendants, get_parent_of_relationships,
+};
+use git2::{Oid, Repository};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+// Name of the file storing the steps left when a sync stops on a conflict, in the git directory.
+const SYNC_STATE_FILE_NAME: &str = "git-stacked-sync";
+
+// A rebase planned by sync: the commits of branch since upstream_oid are replayed on top of
+// the tip of onto, whatever it is when the step runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncStep {
+    pub branch: String,
+    pub upstream_oid: Oid,
+    pub onto: String,
+}
+
+// Plans the rebases bringing every stack up to date with the mainline branch.
+//
+// Stacks forked from an older commit of the mainline are detected as roots: each of them is
+// rebased onto the mainline, then its descendants, depth first, onto their rebased parents.
+// Stacks without common history with the mainline and stacks whose root is already merged into
+// it are left alone. Mainline branches are never rebased.
+pub fn plan_sync(repo: &Repository, mainline: &MainlineBranches) -> Result<Vec<SyncStep>, Error> {
+    let branches = get_branches(repo)?;
+    let Some(mainline_branch) = mainline.find(&branches) else {
+        return Ok(Vec::new());
+    };
+
+    let oids: HashMap<&str, Oid> = branches
+        .iter()
+        .map(|bi| (bi.name.as_str(), bi.oid))
+        .collect();
+    let mut parent_of = get_parent_of_relationships(repo, &branches)?;
+    detach_mainline(&mut parent_of, mainline);
+    let stack = build_children_and_roots(&branches, &parent_of)?;
+
+    let mut merge_bases = MergeBaseCache::new(repo);
+    let mut steps = Vec::new();
+
+    for root in &stack.roots {
+        if mainline.contains(root) {
+            continue;
+        }
+        let root_oid = oids[root.as_str()];
+        let Some(base_oid) = merge_bases.merge_base(root_oid, mainline_branch.oid)? else {
+            continue;
+        };
+        // The root is reachable from the mainline, there's nothing to rebase
+        if base_oid == root_oid {
+            continue;
+        }
+
+        steps.push(SyncStep {
+            branch: root.clone(),
+            upstream_oid: base_oid,
+            onto: mainline_branch.name.clone(),
+        });
+
+        // Descendants come depth first, so each parent is always rebased before its children
+        for descendant in get_descendants(root, &stack.children_map) {
+            if mainline.contains(&descendant) {
+                continue;
+            }
+            let parent_name = parent_of.0[&descendant].clone();
+            steps.push(SyncStep {
+                branch: descendant,
+                upstream_oid: oids[parent_name.as_str()],
+                onto: parent_name,
+            });
+        }
+    }
+
+    Ok(steps)
+}
+
+// Runs the steps in order, updating each branch as soon as it's rebased.
+//
+// On a conflict the branches already rebased stay rebased, the remaining steps are saved and
+// Error::SyncConflict is returned. Once the conflicting branch is rebased by hand, continue_sync
+// runs the rest.
+pub fn sync(repo: &Repository, steps: &[SyncStep]) -> Result<Vec<RestackedBranch>, Error> {
+    ensure_clean_working_tree(repo)?;
+
+    let mut synced = Vec::new();
+
+    for (i, step) in steps.iter().enumerate() {
+        let branch_oid = find_branch_oid(repo, &step.branch)?;
+        let onto_oid = find_branch_oid(repo, &step.onto)?;
+
+        let (new_oid, commits) =
+            match rebase_in_memory(repo, &step.branch, branch_oid, step.upstream_oid, onto_oid) {
+                Ok(result) => result,
+                Err(Error::RebaseConflict(..)) => {
+                    save_state(repo, &steps[i..])?;
+                    return Err(Error::SyncConflict(
+                        step.branch.clone(),
+                        step.onto.clone(),
+                        step.upstream_oid.to_string(),
+                    ));
+                }
+                Err(e) => return Err(e),
+            };
+
+        let rb = RestackedBranch {
+            name: step.branch.clone(),
+            old_oid: branch_oid,
+            new_oid,
+            commits,
+        };
+        update_branches(repo, std::slice::from_ref(&rb))?;
+        synced.push(rb);
+    }
+
+    remove_state(repo)?;
+
+    Ok(synced)
+}
+
+// Resumes a sync stopped on a conflict. The branch which conflicted must have been rebased onto
+// its new parent in the meantime.
+pub fn continue_sync(repo: &Repository) -> Result<Vec<RestackedBranch>, Error> {
+    let steps = load_state(repo)?.ok_or(Error::NoSyncInProgress)?;
+
+    if let Some(step) = steps.first() {
+        let branch_oid = find_branch_oid(repo, &step.branch)?;
+        let onto_oid = find_branch_oid(repo, &step.onto)?;
+        if branch_oid != onto_oid && !repo.graph_descendant_of(branch_oid, onto_oid)? {
+            return Err(Error::SyncConflict(
+                step.branch.clone(),
+                step.onto.clone(),
+                step.upstream_oid.to_string(),
+            ));
+        }
+    }
+
+    sync(repo, steps.get(1..).unwrap_or_default())
+}
+
+// Forgets about a sync stopped on a conflict, the branches already rebased stay rebased.
+pub fn abort_sync(repo: &Repository) -> Result<(), Error> {
+    load_state(repo)?.ok_or(Error::NoSyncInProgress)?;
+
+    remove_state(repo)
+}
+
+// Returns the tip of the local branch branch_name.
+fn find_branch_oid(repo: &Repository, branch_name: &str) -> Result<Oid, Error> {
+    repo.find_branch(branch_name, git2::BranchType::Local)
+        .ok()
+        .and_then(|branch| branch.get().target())
+        .ok_or_else(|| Error::BranchNotFound(branch_name.to_string()))
+}
+
+fn state_path(repo: &Repository) -> PathBuf {
+    repo.path().join(SYNC_STATE_FILE_NAME)
+}
+
+// Writes one step per line: branch, upstream OID and onto, separated by spaces.
+fn save_state(repo: &Repository, steps: &[SyncStep]) -> Result<(), Error> {
+    let data: String = steps
+        .iter()
+        .map(|step| format!("{} {} {}\n", step.branch, step.upstream_oid, step.onto))
+        .collect();
+
+    Ok(std::fs::write(state_path(repo), data)?)
+}
+
+fn load_state(repo: &Repository) -> Result<Option<Vec<SyncStep>>, Error> {
+    let data = match std::fs::read_to_string(state_path(repo)) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(Error::Io(e)),
+    };
+
+    let mut steps = Vec::new();
+    for line in data.lines() {
+        let mut fields = line.split(' ');
+        let (Some(branch), Some(upstream_oid), Some(onto), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(Error::InvalidSyncState(line.to_string()));
+        };
+
+        steps.push(SyncStep {
+            branch: branch.to_string(),
+            upstream_oid: Oid::from_str(upstream_oid)
+                .map_err(|_| Error::InvalidSyncState(line.to_string()))?,
+            onto: onto.to_string(),
+        });
+    }
+
+    Ok(Some(steps))
+}
+
+fn remove_state(repo: &Repository) -> Result<(), Error> {
+    match std::fs::remove_file(state_path(repo)) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(Error::Io(e)),
+    }
+}
@@ -0,0 +1,21 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::get_abbrev_length;
+
+#[test]
+fn core_abbrev_is_used_when_valid() {
+    let test_repo = TestRepo::new();
+    assert_eq!(get_abbrev_length(&test_repo.repo).unwrap(), None);
+
+    let mut config = test_repo.repo.config().unwrap();
+
+    config.set_str("core.abbrev", "12").unwrap();
+    assert_eq!(get_abbrev_length(&test_repo.repo).unwrap(), Some(12));
+
+    config.set_str("core.abbrev", "auto").unwrap();
+    assert_eq!(get_abbrev_length(&test_repo.repo).unwrap(), None);
+
+    config.set_str("core.abbrev", "2").unwrap();
+    assert_eq!(get_abbrev_length(&test_repo.repo).unwrap(), None);
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::{Error, assume_parents, get_branches, get_parent_of_relationships};
+
+// main -> a -> b
+fn linear_stack() -> TestRepo {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    let a = test_repo.commits(root, 1, "a");
+    test_repo.branch("a", a);
+    test_repo.branch("b", test_repo.commits(a, 1, "b"));
+
+    test_repo
+}
+
+fn assumed(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(child, parent)| (child.to_string(), parent.to_string()))
+        .collect()
+}
+
+#[test]
+fn assumed_parent_overrides_detected_one() {
+    let test_repo = linear_stack();
+    let branches = get_branches(&test_repo.repo).unwrap();
+    let mut parent_of = get_parent_of_relationships(&test_repo.repo, &branches).unwrap();
+    assert_eq!(parent_of.0["b"], "a");
+
+    assume_parents(&mut parent_of, &branches, &assumed(&[("b", "main")])).unwrap();
+    assert_eq!(parent_of.0["b"], "main");
+    assert_eq!(parent_of.0["a"], "main");
+}
+
+#[test]
+fn unknown_branch_is_rejected() {
+    let test_repo = linear_stack();
+    let branches = get_branches(&test_repo.repo).unwrap();
+    let mut parent_of = get_parent_of_relationships(&test_repo.repo, &branches).unwrap();
+
+    assert!(matches!(
+        assume_parents(&mut parent_of, &branches, &assumed(&[("b", "missing")])),
+        Err(Error::BranchNotFound(name)) if name == "missing"
+    ));
+    assert!(matches!(
+        assume_parents(&mut parent_of, &branches, &assumed(&[("missing", "a")])),
+        Err(Error::BranchNotFound(name)) if name == "missing"
+    ));
+}
+
+#[test]
+fn cycles_are_rejected() {
+    let test_repo = linear_stack();
+    let branches = get_branches(&test_repo.repo).unwrap();
+    let mut parent_of = get_parent_of_relationships(&test_repo.repo, &branches).unwrap();
+
+    assert!(matches!(
+        assume_parents(&mut parent_of, &branches, &assumed(&[("a", "b")])),
+        Err(Error::CycleDetected(cycle)) if cycle == vec!["a", "b"]
+    ));
+
+    let mut parent_of = get_parent_of_relationships(&test_repo.repo, &branches).unwrap();
+    assert!(matches!(
+        assume_parents(&mut parent_of, &branches, &assumed(&[("a", "a")])),
+        Err(Error::CycleDetected(cycle)) if cycle == vec!["a"]
+    ));
+}
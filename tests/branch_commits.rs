@@ -0,0 +1,42 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::{
+    MainlineBranches, get_branch_commits, get_branches, get_parent_of_relationships,
+};
+
+#[test]
+fn newest_commits_are_listed_up_to_the_limit() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    let a = test_repo.commits(root, 3, "a");
+    test_repo.branch("a", a);
+    let b = test_repo.commits(a, 1, "b");
+    test_repo.branch("b", b);
+
+    let branches = get_branches(&test_repo.repo).unwrap();
+    let parent_of = get_parent_of_relationships(&test_repo.repo, &branches).unwrap();
+    let branch_commits = get_branch_commits(
+        &test_repo.repo,
+        &branches,
+        &parent_of,
+        &MainlineBranches::default(),
+        2,
+    )
+    .unwrap();
+
+    let summaries = |name: &str| -> Vec<String> {
+        branch_commits.0[name]
+            .commits
+            .iter()
+            .map(|commit| commit.summary.clone())
+            .collect()
+    };
+    assert_eq!(summaries("a"), vec!["a 2", "a 1"]);
+    assert_eq!(branch_commits.0["a"].total, 3);
+    assert_eq!(branch_commits.0["b"].commits[0].oid, b);
+    assert_eq!(branch_commits.0["b"].total, 1);
+    assert!(!branch_commits.0.contains_key("main"));
+}
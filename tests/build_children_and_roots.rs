@@ -0,0 +1,87 @@
+use git_stacked::{
+    BranchInfo, Error, MainlineBranches, ParentOfMap, build_children_and_roots, detached_roots,
+    remove_cycles,
+};
+use git2::Oid;
+use std::collections::{HashMap, HashSet};
+
+fn branches(names: &[&str]) -> Vec<BranchInfo> {
+    names
+        .iter()
+        .map(|name| BranchInfo {
+            name: name.to_string(),
+            oid: Oid::zero(),
+        })
+        .collect()
+}
+
+fn parent_of(pairs: &[(&str, &str)]) -> ParentOfMap {
+    ParentOfMap(
+        pairs
+            .iter()
+            .map(|(child, parent)| (child.to_string(), parent.to_string()))
+            .collect::<HashMap<_, _>>(),
+    )
+}
+
+#[test]
+fn cycle_is_detected() {
+    let branches = branches(&["a", "b", "c", "main"]);
+    let parent_of = parent_of(&[("a", "main"), ("b", "c"), ("c", "b")]);
+
+    match build_children_and_roots(&branches, &parent_of) {
+        Err(Error::CycleDetected(cycle)) => assert_eq!(cycle, vec!["b", "c"]),
+        result => panic!("expected a cycle, got {:?}", result),
+    }
+}
+
+#[test]
+fn cycles_are_removed() {
+    let mut branches = branches(&["a", "b", "c", "d", "e", "main"]);
+    let mut parent_of = parent_of(&[
+        ("a", "main"),
+        ("b", "c"),
+        ("c", "b"),
+        ("d", "c"),
+        ("e", "e"),
+    ]);
+
+    let cycles = remove_cycles(&mut branches, &mut parent_of);
+    assert_eq!(cycles, vec![vec!["b", "c"], vec!["e"]]);
+
+    let stack = build_children_and_roots(&branches, &parent_of).unwrap();
+    assert_eq!(stack.roots, vec!["d", "main"]);
+    assert_eq!(stack.children_map.0["main"], vec!["a"]);
+}
+
+#[test]
+fn sort_by_key_keeps_name_order_for_ties() {
+    let branches = branches(&["a", "b", "c", "d", "main", "other"]);
+    let parent_of = parent_of(&[("a", "main"), ("b", "main"), ("c", "main"), ("d", "c")]);
+    let mut stack = build_children_and_roots(&branches, &parent_of).unwrap();
+
+    let ahead: HashMap<&str, usize> = [("a", 1), ("b", 3), ("c", 1), ("main", 2)].into();
+    stack.sort_by_key(|name| std::cmp::Reverse(ahead.get(name).copied().unwrap_or(0)));
+
+    assert_eq!(stack.roots, vec!["main", "other"]);
+    assert_eq!(stack.children_map.0["main"], vec!["b", "a", "c"]);
+    assert_eq!(stack.children_map.0["c"], vec!["d"]);
+}
+
+#[test]
+fn lone_branch_is_a_detached_root_unless_mainline() {
+    let mainline = MainlineBranches::default();
+    let protected = HashSet::new();
+
+    let parent_of = parent_of(&[]);
+    let stack = build_children_and_roots(&branches(&["feature"]), &parent_of).unwrap();
+    assert_eq!(stack.roots, vec!["feature"]);
+    assert_eq!(
+        detached_roots(&stack.roots, &parent_of, &mainline, &protected),
+        HashSet::from(["feature"])
+    );
+
+    let stack = build_children_and_roots(&branches(&["main"]), &parent_of).unwrap();
+    assert_eq!(stack.roots, vec!["main"]);
+    assert!(detached_roots(&stack.roots, &parent_of, &mainline, &protected).is_empty());
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::build_stack;
+
+#[test]
+fn linear_stack() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    let a = test_repo.commits(root, 2, "a");
+    test_repo.branch("a", a);
+    let b = test_repo.commits(a, 1, "b");
+    test_repo.branch("b", b);
+    test_repo.branch("c", test_repo.commits(b, 3, "c"));
+
+    let stack = build_stack(&test_repo.repo).unwrap();
+
+    assert_eq!(stack.roots, vec!["main"]);
+    assert_eq!(stack.children_map.0["main"], vec!["a"]);
+    assert_eq!(stack.children_map.0["a"], vec!["b"]);
+    assert_eq!(stack.children_map.0["b"], vec!["c"]);
+    assert_eq!(stack.children_map.0.len(), 3);
+}
+
+#[test]
+fn fork_with_two_children() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    let base = test_repo.commits(root, 1, "base");
+    test_repo.branch("base", base);
+    test_repo.branch("right", test_repo.commits(base, 1, "right"));
+    test_repo.branch("left", test_repo.commits(base, 2, "left"));
+
+    let stack = build_stack(&test_repo.repo).unwrap();
+
+    assert_eq!(stack.roots, vec!["main"]);
+    assert_eq!(stack.children_map.0["main"], vec!["base"]);
+    assert_eq!(stack.children_map.0["base"], vec!["left", "right"]);
+    assert_eq!(stack.children_map.0.len(), 2);
+}
+
+#[test]
+fn branches_sharing_an_oid() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    let a = test_repo.commits(root, 1, "a");
+    test_repo.branch("a", a);
+    test_repo.branch("a-copy", a);
+    test_repo.branch("main-copy", root);
+    test_repo.branch("child", test_repo.commits(a, 1, "child"));
+
+    let stack = build_stack(&test_repo.repo).unwrap();
+
+    // Each pair is a single node, the mainline branch or the first one by name standing for it
+    assert_eq!(stack.roots, vec!["main"]);
+    assert_eq!(stack.children_map.0["main"], vec!["a"]);
+    assert_eq!(stack.children_map.0["a"], vec!["child"]);
+    assert_eq!(stack.children_map.0.len(), 2);
+    assert_eq!(stack.aliases.0["main"], vec!["main-copy"]);
+    assert_eq!(stack.aliases.0["a"], vec!["a-copy"]);
+    assert_eq!(stack.aliases.0.len(), 2);
+}
+
+#[test]
+fn unrelated_histories_are_separate_roots() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    let orphan = test_repo.commit(&[], "orphan");
+    test_repo.branch("orphan", orphan);
+    test_repo.branch("on-orphan", test_repo.commits(orphan, 1, "on orphan"));
+
+    let stack = build_stack(&test_repo.repo).unwrap();
+
+    assert_eq!(stack.roots, vec!["main", "orphan"]);
+    assert_eq!(stack.children_map.0["orphan"], vec!["on-orphan"]);
+    assert_eq!(stack.children_map.0.len(), 1);
+}
@@ -0,0 +1,79 @@
+#![allow(dead_code)]
+
+use git2::{Oid, Repository, Signature, Time};
+use tempfile::TempDir;
+
+// A throwaway repository to build branch topologies in.
+pub struct TestRepo {
+    pub dir: TempDir,
+    pub repo: Repository,
+    time: std::cell::Cell<i64>,
+}
+
+impl TestRepo {
+    pub fn new() -> Self {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+
+        // Needed by rebases to sign the commits they create
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+
+        TestRepo {
+            dir,
+            repo,
+            time: std::cell::Cell::new(1_700_000_000),
+        }
+    }
+
+    // Creates a commit with the given parents without moving any reference.
+    // Each commit is one minute newer than the previous one.
+    pub fn commit(&self, parents: &[Oid], message: &str) -> Oid {
+        let tree_oid = self.repo.treebuilder(None).unwrap().write().unwrap();
+        self.commit_tree(parents, tree_oid, message)
+    }
+
+    // Creates a commit on top of parent with the tree of parent plus the given top-level files.
+    pub fn commit_files(&self, parent: Oid, files: &[(&str, &str)], message: &str) -> Oid {
+        let parent_tree = self.repo.find_commit(parent).unwrap().tree().unwrap();
+        let mut builder = self.repo.treebuilder(Some(&parent_tree)).unwrap();
+        for (path, content) in files {
+            let blob_oid = self.repo.blob(content.as_bytes()).unwrap();
+            builder.insert(path, blob_oid, 0o100644).unwrap();
+        }
+        let tree_oid = builder.write().unwrap();
+
+        self.commit_tree(&[parent], tree_oid, message)
+    }
+
+    fn commit_tree(&self, parents: &[Oid], tree_oid: Oid, message: &str) -> Oid {
+        let time = self.time.get() + 60;
+        self.time.set(time);
+
+        let signature = Signature::new("test", "test@example.com", &Time::new(time, 0)).unwrap();
+        let tree = self.repo.find_tree(tree_oid).unwrap();
+        let parents: Vec<_> = parents
+            .iter()
+            .map(|oid| self.repo.find_commit(*oid).unwrap())
+            .collect();
+        let parents: Vec<_> = parents.iter().collect();
+
+        self.repo
+            .commit(None, &signature, &signature, message, &tree, &parents)
+            .unwrap()
+    }
+
+    // Creates a chain of n commits on top of parent and returns the last one.
+    pub fn commits(&self, parent: Oid, n: usize, message: &str) -> Oid {
+        (0..n).fold(parent, |oid, i| {
+            self.commit(&[oid], &format!("{} {}", message, i))
+        })
+    }
+
+    // Creates (or moves) the local branch name to oid.
+    pub fn branch(&self, name: &str, oid: Oid) {
+        let commit = self.repo.find_commit(oid).unwrap();
+        self.repo.branch(name, &commit, true).unwrap();
+    }
+}
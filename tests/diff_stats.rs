@@ -0,0 +1,54 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::{
+    DiffStat, MainlineBranches, get_branches, get_diff_stats, get_parent_of_relationships,
+};
+
+#[test]
+fn changes_are_counted_since_the_parent() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit_files(
+        test_repo.commit(&[], "empty"),
+        &[("a.txt", "1\n2\n3\n")],
+        "root",
+    );
+    test_repo.branch("main", root);
+    let a = test_repo.commit_files(root, &[("a.txt", "1\n3\n4\n5\n"), ("b.txt", "b\n")], "a");
+    test_repo.branch("a", a);
+    test_repo.branch(
+        "a-child",
+        test_repo.commit_files(a, &[("c.txt", "c\n")], "child"),
+    );
+
+    let branches = get_branches(&test_repo.repo).unwrap();
+    let parent_of = get_parent_of_relationships(&test_repo.repo, &branches).unwrap();
+    let mainline = MainlineBranches::default();
+
+    let diff_stats =
+        get_diff_stats(&test_repo.repo, &branches, &parent_of, &mainline, None).unwrap();
+    assert_eq!(
+        diff_stats.0["a"],
+        DiffStat {
+            files: 2,
+            insertions: 3,
+            deletions: 1,
+        }
+    );
+    assert_eq!(
+        diff_stats.0["a-child"],
+        DiffStat {
+            files: 1,
+            insertions: 1,
+            deletions: 0,
+        }
+    );
+    assert!(!diff_stats.0.contains_key("main"));
+
+    // a is at depth 1 below main
+    let diff_stats =
+        get_diff_stats(&test_repo.repo, &branches, &parent_of, &mainline, Some(1)).unwrap();
+    assert!(diff_stats.0.contains_key("a"));
+    assert!(!diff_stats.0.contains_key("a-child"));
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::fetch::{fetch_remote, get_upstream_remotes};
+use git_stacked::get_branches;
+
+#[test]
+fn tracked_remotes_are_fetched() {
+    let upstream = TestRepo::new();
+    let root = upstream.commit(&[], "root");
+    let feature = upstream.commits(root, 2, "feature");
+    upstream.branch("main", root);
+    upstream.branch("feature", feature);
+
+    let test_repo = TestRepo::new();
+    test_repo.branch("main", test_repo.commit(&[], "local"));
+    test_repo.branch("other", test_repo.commit(&[], "other"));
+    test_repo
+        .repo
+        .remote("origin", upstream.dir.path().to_str().unwrap())
+        .unwrap();
+    test_repo.repo.remote("unused", "/nonexistent").unwrap();
+
+    let mut config = test_repo.repo.config().unwrap();
+    config.set_str("branch.main.remote", "origin").unwrap();
+    config
+        .set_str("branch.main.merge", "refs/heads/main")
+        .unwrap();
+    // Tracking a local branch doesn't involve any remote
+    config.set_str("branch.other.remote", ".").unwrap();
+    config
+        .set_str("branch.other.merge", "refs/heads/main")
+        .unwrap();
+
+    let branches = get_branches(&test_repo.repo).unwrap();
+    let remotes = get_upstream_remotes(&test_repo.repo, &branches).unwrap();
+    assert_eq!(remotes, vec!["origin"]);
+
+    fetch_remote(&test_repo.repo, "origin").unwrap();
+    let fetched = test_repo
+        .repo
+        .find_reference("refs/remotes/origin/feature")
+        .unwrap();
+    assert_eq!(fetched.target(), Some(feature));
+}
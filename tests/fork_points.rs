@@ -0,0 +1,33 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::{ForkPoint, build_stack, get_branches, get_fork_points};
+
+#[test]
+fn siblings_diverge_from_their_merge_base() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    let fork = test_repo.commits(root, 1, "base");
+    test_repo.branch("base", fork);
+    test_repo.branch("left", test_repo.commits(fork, 2, "left"));
+    let right = test_repo.commits(fork, 1, "right");
+    test_repo.branch("right", right);
+    test_repo.branch("right-child", test_repo.commits(right, 1, "child"));
+
+    let branches = get_branches(&test_repo.repo).unwrap();
+    let stack = build_stack(&test_repo.repo).unwrap();
+    let fork_points = get_fork_points(&test_repo.repo, &branches, &stack.children_map).unwrap();
+
+    assert_eq!(
+        fork_points.0["base"],
+        ForkPoint {
+            oid: fork,
+            diverged: vec![("left".to_string(), 2), ("right".to_string(), 1)],
+        }
+    );
+    // Only children
+    assert!(!fork_points.0.contains_key("main"));
+    assert!(!fork_points.0.contains_key("right"));
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::{BranchOptions, get_branches, get_branches_with_options};
+
+#[test]
+fn symbolic_branch_is_resolved() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    let feature = test_repo.commits(root, 1, "feature");
+    test_repo.branch("feature", feature);
+    test_repo
+        .repo
+        .reference_symbolic("refs/heads/alias", "refs/heads/feature", false, "test")
+        .unwrap();
+    test_repo
+        .repo
+        .reference_symbolic("refs/heads/dangling", "refs/heads/missing", false, "test")
+        .unwrap();
+
+    let branches = get_branches(&test_repo.repo).unwrap();
+    let names: Vec<_> = branches.iter().map(|bi| bi.name.as_str()).collect();
+    assert_eq!(names, vec!["alias", "feature", "main"]);
+    assert_eq!(branches[0].oid, feature);
+}
+
+// Non UTF-8 file names can only be created on Unix
+#[cfg(unix)]
+#[test]
+fn non_utf8_branch_names_are_kept_in_lossy_mode() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    // git2 only creates references with UTF-8 names, write the loose ref by hand
+    let ref_path = test_repo
+        .repo
+        .path()
+        .join("refs")
+        .join("heads")
+        .join(std::ffi::OsStr::from_bytes(b"caf\xe9"));
+    std::fs::write(ref_path, format!("{}\n", root)).unwrap();
+
+    let names: Vec<_> = get_branches(&test_repo.repo)
+        .unwrap()
+        .into_iter()
+        .map(|bi| bi.name)
+        .collect();
+    assert_eq!(names, vec!["main"]);
+
+    let options = BranchOptions { lossy_names: true };
+    let names: Vec<_> = get_branches_with_options(&test_repo.repo, &options)
+        .unwrap()
+        .into_iter()
+        .map(|bi| bi.name)
+        .collect();
+    assert_eq!(names, vec!["caf\u{fffd}", "main"]);
+}
@@ -0,0 +1,35 @@
+use git_stacked::{BranchInfo, MainlineBranches, group_aliases};
+use git2::Oid;
+
+fn branches(branches: &[(&str, u8)]) -> Vec<BranchInfo> {
+    branches
+        .iter()
+        .map(|(name, oid)| BranchInfo {
+            name: name.to_string(),
+            oid: Oid::from_bytes(&[*oid; 20]).unwrap(),
+        })
+        .collect()
+}
+
+#[test]
+fn same_tip_branches_are_grouped() {
+    let mut branches = branches(&[
+        ("a-new", 1),
+        ("feature", 2),
+        ("feature-copy", 2),
+        ("main", 1),
+        ("other", 3),
+    ]);
+
+    let aliases = group_aliases(&mut branches, &MainlineBranches::default());
+
+    let names: Vec<_> = branches.iter().map(|bi| bi.name.as_str()).collect();
+    assert_eq!(names, vec!["feature", "main", "other"]);
+    assert_eq!(aliases.0["main"], vec!["a-new"]);
+    assert_eq!(aliases.0["feature"], vec!["feature-copy"]);
+    assert_eq!(aliases.0.len(), 2);
+
+    assert_eq!(aliases.representative("a-new"), "main");
+    assert_eq!(aliases.representative("feature-copy"), "feature");
+    assert_eq!(aliases.representative("other"), "other");
+}
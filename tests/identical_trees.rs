@@ -0,0 +1,28 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::{get_branches, get_identical_trees};
+
+#[test]
+fn branches_with_the_same_tree_are_grouped() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    let original = test_repo.commit_files(root, &[("a", "1")], "original");
+    test_repo.branch("original", original);
+    test_repo.branch("same", original);
+    test_repo.branch(
+        "rebased",
+        test_repo.commit_files(root, &[("a", "1")], "amended"),
+    );
+    test_repo.branch(
+        "different",
+        test_repo.commit_files(root, &[("a", "2")], "different"),
+    );
+
+    let branches = get_branches(&test_repo.repo).unwrap();
+    let groups = get_identical_trees(&test_repo.repo, &branches).unwrap();
+
+    assert_eq!(groups, vec![vec!["original", "rebased", "same"]]);
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::{Error, IGNORE_FILE_NAME, get_branches, get_ignore_patterns};
+
+#[test]
+fn patterns_are_read() {
+    let test_repo = TestRepo::new();
+
+    std::fs::write(
+        test_repo.dir.path().join(IGNORE_FILE_NAME),
+        "# bots\ndependabot/*\n\n  wip  \n",
+    )
+    .unwrap();
+
+    let patterns: Vec<_> = get_ignore_patterns(&test_repo.repo)
+        .unwrap()
+        .into_iter()
+        .map(|pattern| pattern.as_str().to_string())
+        .collect();
+    assert_eq!(patterns, vec!["dependabot/*", "wip"]);
+}
+
+#[test]
+fn missing_file_has_no_patterns() {
+    let test_repo = TestRepo::new();
+
+    assert!(get_ignore_patterns(&test_repo.repo).unwrap().is_empty());
+}
+
+#[test]
+fn ignored_branches_are_still_listed() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    test_repo.branch("feature", root);
+    test_repo.branch("wip", root);
+
+    std::fs::write(test_repo.dir.path().join(IGNORE_FILE_NAME), "wip\n").unwrap();
+
+    // Only the display hides them, the commands changing branches must still see them
+    let names: Vec<_> = get_branches(&test_repo.repo)
+        .unwrap()
+        .into_iter()
+        .map(|bi| bi.name)
+        .collect();
+    assert_eq!(names, vec!["feature", "main", "wip"]);
+}
+
+#[test]
+fn invalid_pattern_is_reported() {
+    let test_repo = TestRepo::new();
+
+    std::fs::write(test_repo.dir.path().join(IGNORE_FILE_NAME), "[\n").unwrap();
+
+    assert!(matches!(
+        get_ignore_patterns(&test_repo.repo),
+        Err(Error::InvalidIgnoreFile(..))
+    ));
+}
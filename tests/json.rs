@@ -0,0 +1,41 @@
+use git_stacked::json::{JSON_FORMAT_VERSION, JsonTree};
+use git_stacked::{AliasMap, BranchInfo, ChildrenMap};
+use git2::Oid;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+#[test]
+fn json_tree_is_versioned_and_round_trips() {
+    let branches: Vec<BranchInfo> = [("a", 1), ("b", 2), ("main", 3), ("orphan", 4)]
+        .iter()
+        .map(|(name, oid)| BranchInfo {
+            name: name.to_string(),
+            oid: Oid::from_bytes(&[*oid; 20]).unwrap(),
+        })
+        .collect();
+    let children_map = ChildrenMap(BTreeMap::from([
+        ("main".to_string(), vec!["a".to_string()]),
+        ("a".to_string(), vec!["b".to_string()]),
+    ]));
+    let roots = vec!["main".to_string(), "orphan".to_string()];
+    let detached_roots = HashSet::from(["orphan"]);
+    let aliases = AliasMap(HashMap::from([(
+        "b".to_string(),
+        vec!["b-copy".to_string()],
+    )]));
+
+    let tree = JsonTree::build(&branches, &children_map, &roots, &detached_roots, &aliases);
+    let json = serde_json::to_value(&tree).unwrap();
+
+    assert_eq!(json["version"], JSON_FORMAT_VERSION);
+    assert_eq!(json["roots"][0]["name"], "main");
+    assert_eq!(json["roots"][0]["detached"], false);
+    assert_eq!(
+        json["roots"][0]["children"][0]["children"][0]["aliases"][0],
+        "b-copy"
+    );
+    assert_eq!(json["roots"][1]["detached"], true);
+    assert!(json["roots"][1].get("aliases").is_none());
+
+    let round_tripped: JsonTree = serde_json::from_value(json).unwrap();
+    assert_eq!(round_tripped, tree);
+}
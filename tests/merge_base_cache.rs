@@ -0,0 +1,23 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::MergeBaseCache;
+
+#[test]
+fn merge_bases_are_computed_once_per_pair() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    let a = test_repo.commits(root, 2, "a");
+    let b = test_repo.commits(root, 3, "b");
+    let orphan = test_repo.commit(&[], "orphan");
+
+    let mut cache = MergeBaseCache::new(&test_repo.repo);
+    assert_eq!(cache.merge_base(a, b).unwrap(), Some(root));
+    assert_eq!(cache.merge_base(b, a).unwrap(), Some(root));
+    assert_eq!(cache.len(), 1);
+
+    assert_eq!(cache.merge_base(a, orphan).unwrap(), None);
+    assert_eq!(cache.merge_base(orphan, a).unwrap(), None);
+    assert_eq!(cache.len(), 2);
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::{
+    MainlineBranches, get_branches, get_merge_readiness, get_parent_of_relationships,
+};
+
+#[test]
+fn branches_are_ready_when_their_parents_are_merged() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    let a = test_repo.commits(root, 1, "a");
+    let b = test_repo.commits(a, 1, "b");
+    test_repo.branch("a", a);
+    test_repo.branch("b", b);
+    test_repo.branch("c", test_repo.commits(b, 1, "c"));
+    // b is merged, a is implicitly merged since it's part of b
+    test_repo.branch("main", test_repo.commit(&[root, b], "merge b"));
+
+    let branches = get_branches(&test_repo.repo).unwrap();
+    let parent_of = get_parent_of_relationships(&test_repo.repo, &branches).unwrap();
+    let mainline = MainlineBranches::default();
+    let target = mainline.find(&branches).unwrap();
+
+    let readiness =
+        get_merge_readiness(&test_repo.repo, &branches, &parent_of, &mainline, target).unwrap();
+    assert!(readiness.0["a"]);
+    assert!(readiness.0["b"]);
+    assert!(readiness.0["c"]);
+    assert!(!readiness.0.contains_key("main"));
+
+    let d = test_repo.commits(root, 1, "d");
+    test_repo.branch("d", d);
+    test_repo.branch("e", test_repo.commits(d, 1, "e"));
+
+    let branches = get_branches(&test_repo.repo).unwrap();
+    let parent_of = get_parent_of_relationships(&test_repo.repo, &branches).unwrap();
+    let target = mainline.find(&branches).unwrap();
+
+    let readiness =
+        get_merge_readiness(&test_repo.repo, &branches, &parent_of, &mainline, target).unwrap();
+    assert!(readiness.0["d"]);
+    assert!(!readiness.0["e"]);
+}
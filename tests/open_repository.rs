@@ -0,0 +1,65 @@
+use git_stacked::{Error, build_stack, get_current_branch, is_empty_repository, open_repository};
+use git2::Repository;
+use tempfile::TempDir;
+
+#[test]
+fn outside_a_repository() {
+    let dir = TempDir::new().unwrap();
+
+    match open_repository(dir.path()) {
+        Err(Error::NotARepository) => {}
+        Err(err) => panic!("unexpected error: {:?}", err),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+#[test]
+fn bare_repository() {
+    let dir = TempDir::new().unwrap();
+    Repository::init_bare(dir.path()).unwrap();
+
+    match open_repository(dir.path()) {
+        Err(Error::RepositoryIsBare) => {}
+        Err(err) => panic!("unexpected error: {:?}", err),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+#[test]
+fn from_a_subdirectory() {
+    let dir = TempDir::new().unwrap();
+    Repository::init(dir.path()).unwrap();
+    let subdir = dir.path().join("a").join("b");
+    std::fs::create_dir_all(&subdir).unwrap();
+
+    let repo = open_repository(&subdir).unwrap();
+    assert_eq!(
+        repo.workdir().unwrap().canonicalize().unwrap(),
+        dir.path().canonicalize().unwrap()
+    );
+}
+
+#[test]
+fn missing_path() {
+    let dir = TempDir::new().unwrap();
+
+    match open_repository(&dir.path().join("missing")) {
+        Err(Error::NotARepository) => {}
+        Err(err) => panic!("unexpected error: {:?}", err),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+#[test]
+fn empty_repository() {
+    let dir = TempDir::new().unwrap();
+    Repository::init(dir.path()).unwrap();
+
+    let repo = open_repository(dir.path()).unwrap();
+    assert!(is_empty_repository(&repo).unwrap());
+    assert_eq!(get_current_branch(&repo).unwrap(), None);
+
+    let stack = build_stack(&repo).unwrap();
+    assert!(stack.roots.is_empty());
+    assert!(stack.children_map.0.is_empty());
+}
@@ -0,0 +1,307 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::{
+    AmbiguousParent, BranchInfo, MainlineBranches, ParentDetectionOptions, ParentOfMap, ParentRule,
+    detach_mainline, get_branches, get_parent_of_relationships,
+    get_parent_of_relationships_with_warnings, get_remote_branches,
+};
+use git2::{ErrorCode, Oid, Repository};
+use std::collections::HashMap;
+use std::time::Instant;
+
+// The original pairwise merge-base parent detection, kept as a reference for the revwalk based one.
+fn get_parent_of_relationships_pairwise(repo: &Repository, branches: &[BranchInfo]) -> ParentOfMap {
+    let mut parent_of = ParentOfMap(HashMap::new());
+
+    for child_branch_info in branches {
+        let child_name = &child_branch_info.name;
+        let child_oid = child_branch_info.oid;
+
+        let mut current_best_parent_name: Option<String> = None;
+        let mut current_best_parent_oid: Option<Oid> = None;
+
+        for potential_parent_info in branches {
+            let potential_parent_name = &potential_parent_info.name;
+            let potential_parent_oid = potential_parent_info.oid;
+
+            if child_name == potential_parent_name || potential_parent_oid == child_oid {
+                continue;
+            }
+
+            match repo.merge_base(potential_parent_oid, child_oid) {
+                Ok(base_oid) if base_oid == potential_parent_oid => {
+                    if current_best_parent_name.is_none() {
+                        current_best_parent_name = Some(potential_parent_name.clone());
+                        current_best_parent_oid = Some(potential_parent_oid);
+                    } else if let Some(cbp_oid) = current_best_parent_oid
+                        && cbp_oid != potential_parent_oid
+                    {
+                        match repo.merge_base(cbp_oid, potential_parent_oid) {
+                            Ok(base_between_parents_oid) if base_between_parents_oid == cbp_oid => {
+                                current_best_parent_name = Some(potential_parent_name.clone());
+                                current_best_parent_oid = Some(potential_parent_oid);
+                            }
+                            Err(e) if e.code() == ErrorCode::NotFound => {}
+                            Err(e) => panic!("merge base failed: {}", e),
+                            _ => {}
+                        }
+                    }
+                }
+                Err(e) if e.code() == ErrorCode::NotFound => {}
+                Err(e) => panic!("merge base failed: {}", e),
+                _ => {}
+            }
+        }
+        if let Some(p_name) = current_best_parent_name {
+            parent_of.0.insert(child_name.clone(), p_name);
+        }
+    }
+
+    parent_of
+}
+
+// Builds a pseudo-random forest of stacked branches on top of a mainline, including branches
+// sharing a tip, merges of the mainline into branches and an unrelated history.
+fn build_forest(num_branches: usize) -> TestRepo {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    let mut main_oid = test_repo.commits(root, 20, "main");
+    test_repo.branch("main", main_oid);
+
+    let orphan = test_repo.commit(&[], "orphan");
+    test_repo.branch("orphan", test_repo.commits(orphan, 3, "orphan"));
+
+    let mut tips: Vec<Oid> = vec![main_oid];
+    let mut seed: u64 = 42;
+    let mut next = |n: usize| {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((seed >> 33) as usize) % n
+    };
+
+    for i in 0..num_branches {
+        let base = tips[next(tips.len())];
+        let tip = match next(10) {
+            0 => base,
+            1 => {
+                let tip = test_repo.commits(base, 1 + next(3), "feature");
+                test_repo.commit(&[tip, main_oid], "merge main")
+            }
+            _ => test_repo.commits(base, 1 + next(3), "feature"),
+        };
+        test_repo.branch(&format!("feature-{:04}", i), tip);
+        tips.push(tip);
+
+        if i % 10 == 0 {
+            main_oid = test_repo.commits(main_oid, 1, "main");
+            test_repo.branch("main", main_oid);
+        }
+    }
+
+    test_repo
+}
+
+#[test]
+fn revwalk_detection_matches_pairwise_merge_base() {
+    let test_repo = build_forest(60);
+    let branches = get_branches(&test_repo.repo).unwrap();
+
+    let expected = get_parent_of_relationships_pairwise(&test_repo.repo, &branches);
+    let parent_of = get_parent_of_relationships(&test_repo.repo, &branches).unwrap();
+
+    assert_eq!(parent_of.0, expected.0);
+}
+
+// Run with `cargo test --release --test parent_detection -- --ignored --nocapture`
+#[test]
+#[ignore]
+fn bench_parent_detection() {
+    let test_repo = build_forest(400);
+    let branches = get_branches(&test_repo.repo).unwrap();
+
+    let start = Instant::now();
+    let expected = get_parent_of_relationships_pairwise(&test_repo.repo, &branches);
+    let pairwise_elapsed = start.elapsed();
+
+    let start = Instant::now();
+    let parent_of = get_parent_of_relationships(&test_repo.repo, &branches).unwrap();
+    let revwalk_elapsed = start.elapsed();
+
+    println!(
+        "{} branches: pairwise merge-base {:?}, revwalk {:?}",
+        branches.len(),
+        pairwise_elapsed,
+        revwalk_elapsed
+    );
+
+    assert_eq!(parent_of.0, expected.0);
+    assert!(revwalk_elapsed < pairwise_elapsed);
+}
+
+#[test]
+fn local_branch_is_preferred_over_remote_branch_with_same_tip() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    let zeta = test_repo.commits(root, 1, "zeta");
+    test_repo.branch("zeta", zeta);
+    test_repo
+        .repo
+        .reference("refs/remotes/origin/zeta", zeta, false, "test")
+        .unwrap();
+    test_repo.branch("zeta-child", test_repo.commits(zeta, 1, "child"));
+
+    let mut branches = get_branches(&test_repo.repo).unwrap();
+    branches.extend(get_remote_branches(&test_repo.repo).unwrap());
+
+    let parent_of = get_parent_of_relationships(&test_repo.repo, &branches).unwrap();
+    assert_eq!(parent_of.0["zeta-child"], "zeta");
+    assert_eq!(parent_of.0["zeta"], "main");
+    assert_eq!(parent_of.0["remotes/origin/zeta"], "main");
+}
+
+#[test]
+fn unrelated_candidate_parents_are_reported() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    let left = test_repo.commits(root, 1, "left");
+    let right = test_repo.commits(root, 1, "right");
+    test_repo.branch("left", left);
+    test_repo.branch("right", right);
+    test_repo.branch("merged", test_repo.commit(&[left, right], "merge"));
+
+    let branches = get_branches(&test_repo.repo).unwrap();
+    let (parent_of, warnings) = get_parent_of_relationships_with_warnings(
+        &test_repo.repo,
+        &branches,
+        &ParentDetectionOptions::default(),
+    )
+    .unwrap();
+
+    assert_eq!(parent_of.0["merged"], "left");
+    assert_eq!(
+        warnings,
+        vec![AmbiguousParent {
+            name: "merged".to_string(),
+            parent: "left".to_string(),
+            other: "right".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn first_parent_ignores_merged_in_branches() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    let other = test_repo.commits(root, 1, "other");
+    test_repo.branch("other", other);
+    let feature = test_repo.commits(root, 1, "feature");
+    test_repo.branch(
+        "feature",
+        test_repo.commit(&[feature, other], "merge other"),
+    );
+
+    let branches = get_branches(&test_repo.repo).unwrap();
+
+    let parent_of = get_parent_of_relationships(&test_repo.repo, &branches).unwrap();
+    assert_eq!(parent_of.0["feature"], "other");
+
+    let options = ParentDetectionOptions {
+        first_parent: true,
+        ..ParentDetectionOptions::default()
+    };
+    let (parent_of, warnings) =
+        get_parent_of_relationships_with_warnings(&test_repo.repo, &branches, &options).unwrap();
+    assert_eq!(parent_of.0["feature"], "main");
+    assert!(warnings.is_empty());
+}
+
+#[test]
+fn configured_parent_overrides_inferred_parent() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    let a = test_repo.commits(root, 1, "a");
+    test_repo.branch("a", a);
+    test_repo.branch("b", test_repo.commits(a, 1, "b"));
+    test_repo.branch("c", test_repo.commits(root, 1, "c"));
+
+    let mut config = test_repo.repo.config().unwrap();
+    config.set_str("branch.b.stacked-parent", "main").unwrap();
+    config
+        .set_str("branch.c.stacked-parent", "missing")
+        .unwrap();
+
+    let branches = get_branches(&test_repo.repo).unwrap();
+    let parent_of = get_parent_of_relationships(&test_repo.repo, &branches).unwrap();
+
+    assert_eq!(parent_of.0["b"], "main");
+    assert_eq!(parent_of.0["a"], "main");
+    assert_eq!(parent_of.0["c"], "main");
+}
+
+#[test]
+fn latest_rule_picks_most_recent_unrelated_candidate() {
+    let test_repo = TestRepo::new();
+
+    // A diamond: left and right both fork from main and are merged back together
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    let left = test_repo.commits(root, 1, "left");
+    let right = test_repo.commits(root, 1, "right");
+    test_repo.branch("left", left);
+    test_repo.branch("right", right);
+    let merged = test_repo.commit(&[left, right], "merge");
+    test_repo.branch("merged", merged);
+    test_repo.branch("merged-child", test_repo.commits(merged, 1, "child"));
+
+    let branches = get_branches(&test_repo.repo).unwrap();
+
+    let nearest = get_parent_of_relationships(&test_repo.repo, &branches).unwrap();
+    assert_eq!(nearest.0["merged"], "left");
+
+    let options = ParentDetectionOptions {
+        rule: ParentRule::Latest,
+        ..ParentDetectionOptions::default()
+    };
+    let (latest, warnings) =
+        get_parent_of_relationships_with_warnings(&test_repo.repo, &branches, &options).unwrap();
+    assert_eq!(latest.0["merged"], "right");
+    // Descendants of the candidates still win over them
+    assert_eq!(latest.0["merged-child"], "merged");
+    assert_eq!(latest.0["left"], "main");
+    assert_eq!(
+        warnings,
+        vec![AmbiguousParent {
+            name: "merged".to_string(),
+            parent: "right".to_string(),
+            other: "left".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn mainline_branched_off_another_branch_is_detached() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    let feature = test_repo.commits(root, 1, "feature");
+    test_repo.branch("feature", feature);
+    test_repo.branch("main", test_repo.commits(feature, 1, "main"));
+
+    let branches = get_branches(&test_repo.repo).unwrap();
+    let mut parent_of = get_parent_of_relationships(&test_repo.repo, &branches).unwrap();
+    assert_eq!(parent_of.0["main"], "feature");
+
+    detach_mainline(&mut parent_of, &MainlineBranches::default());
+    assert!(parent_of.0.is_empty());
+}
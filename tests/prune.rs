@@ -0,0 +1,33 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::prune::{delete_branches, get_merged_branches};
+use git_stacked::{MainlineBranches, get_branches};
+
+#[test]
+fn merged_branches_are_pruned() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    let merged = test_repo.commits(root, 2, "merged");
+    let main = test_repo.commits(merged, 2, "main");
+    test_repo.branch("main", main);
+    test_repo.branch("develop", merged);
+    test_repo.branch("merged", merged);
+    test_repo.branch("current", root);
+    test_repo.branch("unmerged", test_repo.commits(root, 1, "unmerged"));
+    test_repo.repo.set_head("refs/heads/current").unwrap();
+
+    let mainline = MainlineBranches::default();
+    let pruned = get_merged_branches(&test_repo.repo, &mainline).unwrap();
+    let names: Vec<_> = pruned.iter().map(|bi| bi.name.as_str()).collect();
+    assert_eq!(names, vec!["merged"]);
+
+    delete_branches(&test_repo.repo, &pruned).unwrap();
+    let names: Vec<_> = get_branches(&test_repo.repo)
+        .unwrap()
+        .into_iter()
+        .map(|bi| bi.name)
+        .collect();
+    assert_eq!(names, vec!["current", "develop", "main", "unmerged"]);
+}
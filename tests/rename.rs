@@ -0,0 +1,93 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::rename::rename_branch;
+use git_stacked::{Error, IGNORE_FILE_NAME, get_branches};
+
+#[test]
+fn branch_is_renamed() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    test_repo.branch("old", test_repo.commits(root, 1, "old"));
+
+    rename_branch(&test_repo.repo, "old", "new").unwrap();
+
+    let names: Vec<_> = get_branches(&test_repo.repo)
+        .unwrap()
+        .into_iter()
+        .map(|bi| bi.name)
+        .collect();
+    assert_eq!(names, vec!["main", "new"]);
+}
+
+#[test]
+fn rename_is_validated() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    test_repo.branch("old", root);
+
+    assert!(matches!(
+        rename_branch(&test_repo.repo, "old", "main"),
+        Err(Error::BranchExists(name)) if name == "main"
+    ));
+    assert!(matches!(
+        rename_branch(&test_repo.repo, "old", "bad..name"),
+        Err(Error::InvalidBranchName(_))
+    ));
+    assert!(matches!(
+        rename_branch(&test_repo.repo, "missing", "new"),
+        Err(Error::BranchNotFound(_))
+    ));
+}
+
+#[test]
+fn recorded_parents_follow_the_rename() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    test_repo.branch("old", test_repo.commits(root, 1, "old"));
+    test_repo.branch("child", test_repo.commits(root, 1, "child"));
+
+    let mut config = test_repo.repo.config().unwrap();
+    config
+        .set_str("branch.child.stacked-parent", "old")
+        .unwrap();
+
+    rename_branch(&test_repo.repo, "old", "new").unwrap();
+
+    let config = test_repo.repo.config().unwrap().snapshot().unwrap();
+    assert_eq!(
+        config.get_str("branch.child.stacked-parent").unwrap(),
+        "new"
+    );
+}
+
+#[test]
+fn ignored_children_follow_the_rename() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    test_repo.branch("old", test_repo.commits(root, 1, "old"));
+    test_repo.branch("wip/child", test_repo.commits(root, 1, "child"));
+
+    std::fs::write(test_repo.dir.path().join(IGNORE_FILE_NAME), "wip/*\n").unwrap();
+
+    let mut config = test_repo.repo.config().unwrap();
+    config
+        .set_str("branch.wip/child.stacked-parent", "old")
+        .unwrap();
+
+    rename_branch(&test_repo.repo, "old", "new").unwrap();
+
+    let config = test_repo.repo.config().unwrap().snapshot().unwrap();
+    assert_eq!(
+        config.get_str("branch.wip/child.stacked-parent").unwrap(),
+        "new"
+    );
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::restack::restack;
+use git_stacked::{Error, get_branches, get_parent_of_relationships};
+
+// A stack feature -> child forked from main, next to a base branch also forked from main and
+// adding base_files. main is checked out.
+fn stack_next_to_base(base_files: &[(&str, &str)]) -> TestRepo {
+    let test_repo = TestRepo::new();
+    test_repo.repo.set_head("refs/heads/main").unwrap();
+
+    let main = test_repo.commit_files(test_repo.commit(&[], "root"), &[("main", "1")], "main");
+    test_repo.branch("main", main);
+    let feature = test_repo.commit_files(main, &[("feature", "1")], "feature");
+    test_repo.branch("feature", feature);
+    test_repo.branch(
+        "child",
+        test_repo.commit_files(feature, &[("child", "1")], "child"),
+    );
+    test_repo.branch("base", test_repo.commit_files(main, base_files, "base"));
+    test_repo
+        .repo
+        .checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+        .unwrap();
+
+    test_repo
+}
+
+fn tip(test_repo: &TestRepo, name: &str) -> git2::Oid {
+    test_repo.repo.revparse_single(name).unwrap().id()
+}
+
+#[test]
+fn branch_is_rebased_onto_new_parent() {
+    let test_repo = stack_next_to_base(&[("base", "1")]);
+    let repo = &test_repo.repo;
+    let child = tip(&test_repo, "child");
+
+    let restacked = restack(repo, "feature", "base", false).unwrap();
+    assert_eq!(restacked.len(), 1);
+    assert_eq!(restacked[0].name, "feature");
+    assert_eq!(restacked[0].commits, 1);
+    assert_eq!(restacked[0].new_oid, tip(&test_repo, "feature"));
+
+    // The child isn't moved and now forks from the old feature commits
+    assert_eq!(tip(&test_repo, "child"), child);
+    let branches = get_branches(repo).unwrap();
+    let parent_of = get_parent_of_relationships(repo, &branches).unwrap();
+    assert_eq!(parent_of.0["feature"], "base");
+    assert_eq!(parent_of.0["child"], "main");
+}
+
+#[test]
+fn descendants_are_rebased_onto_their_rebased_parents() {
+    let test_repo = stack_next_to_base(&[("base", "1")]);
+    let repo = &test_repo.repo;
+
+    let restacked = restack(repo, "feature", "base", true).unwrap();
+    let names: Vec<_> = restacked.iter().map(|rb| rb.name.as_str()).collect();
+    assert_eq!(names, vec!["feature", "child"]);
+    assert!(restacked.iter().all(|rb| rb.commits == 1));
+
+    let branches = get_branches(repo).unwrap();
+    let parent_of = get_parent_of_relationships(repo, &branches).unwrap();
+    assert_eq!(parent_of.0["feature"], "base");
+    assert_eq!(parent_of.0["child"], "feature");
+}
+
+#[test]
+fn dirty_working_tree_is_refused() {
+    let test_repo = stack_next_to_base(&[("base", "1")]);
+    let feature = tip(&test_repo, "feature");
+    std::fs::write(test_repo.dir.path().join("main"), "2").unwrap();
+
+    assert!(matches!(
+        restack(&test_repo.repo, "feature", "base", true),
+        Err(Error::DirtyWorkingTree)
+    ));
+    assert_eq!(tip(&test_repo, "feature"), feature);
+}
+
+#[test]
+fn conflict_leaves_every_branch_unchanged() {
+    // feature is replayed cleanly on base, child conflicts with it
+    let test_repo = stack_next_to_base(&[("child", "2")]);
+    let feature = tip(&test_repo, "feature");
+    let child = tip(&test_repo, "child");
+    let base = tip(&test_repo, "base");
+
+    match restack(&test_repo.repo, "feature", "base", true) {
+        Err(Error::RebaseConflict(branch, _)) => assert_eq!(branch, "child"),
+        result => panic!("expected a conflict, got {:?}", result),
+    }
+    assert_eq!(tip(&test_repo, "feature"), feature);
+    assert_eq!(tip(&test_repo, "child"), child);
+    assert_eq!(tip(&test_repo, "base"), base);
+}
+
+#[test]
+fn new_parent_cannot_be_a_descendant() {
+    let test_repo = stack_next_to_base(&[("base", "1")]);
+    let feature = tip(&test_repo, "feature");
+
+    assert!(matches!(
+        restack(&test_repo.repo, "feature", "child", true),
+        Err(Error::InvalidRestack(..))
+    ));
+    assert!(matches!(
+        restack(&test_repo.repo, "feature", "feature", false),
+        Err(Error::InvalidRestack(..))
+    ));
+    assert_eq!(tip(&test_repo, "feature"), feature);
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::parent::set_parent;
+use git_stacked::{Error, get_branches, get_parent_of_relationships};
+
+#[test]
+fn parent_is_recorded() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    let a = test_repo.commits(root, 1, "a");
+    test_repo.branch("a", a);
+    test_repo.branch("b", test_repo.commits(a, 1, "b"));
+
+    set_parent(&test_repo.repo, "b", "main").unwrap();
+
+    let branches = get_branches(&test_repo.repo).unwrap();
+    let parent_of = get_parent_of_relationships(&test_repo.repo, &branches).unwrap();
+    assert_eq!(parent_of.0["b"], "main");
+}
+
+#[test]
+fn cycles_and_missing_branches_are_rejected() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    let a = test_repo.commits(root, 1, "a");
+    test_repo.branch("a", a);
+    test_repo.branch("b", test_repo.commits(a, 1, "b"));
+
+    assert!(matches!(
+        set_parent(&test_repo.repo, "a", "b"),
+        Err(Error::CycleDetected(cycle)) if cycle == vec!["a", "b"]
+    ));
+    assert!(matches!(
+        set_parent(&test_repo.repo, "b", "missing"),
+        Err(Error::BranchNotFound(name)) if name == "missing"
+    ));
+    assert!(
+        test_repo
+            .repo
+            .config()
+            .unwrap()
+            .get_string("branch.a.stacked-parent")
+            .is_err()
+    );
+}
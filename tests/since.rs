@@ -0,0 +1,59 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::{
+    MainlineBranches, get_branches, get_commit_logs, parse_date, remove_inactive_branches,
+};
+
+const NOW: i64 = 1_700_000_000;
+
+#[test]
+fn relative_dates() {
+    assert_eq!(parse_date("2.weeks", NOW), Some(NOW - 14 * 24 * 60 * 60));
+    assert_eq!(parse_date("3 days ago", NOW), Some(NOW - 3 * 24 * 60 * 60));
+    assert_eq!(parse_date("1.hour.ago", NOW), Some(NOW - 60 * 60));
+    assert_eq!(parse_date("2.fortnights", NOW), None);
+    assert_eq!(parse_date("2 days from now", NOW), None);
+    assert_eq!(parse_date("-2.days", NOW), None);
+    assert_eq!(parse_date("9223372036854775807.seconds", -NOW), None);
+}
+
+#[test]
+fn iso_dates() {
+    assert_eq!(parse_date("1970-01-01", NOW), Some(0));
+    assert_eq!(parse_date("2023-11-14", NOW), Some(1_699_920_000));
+    assert_eq!(parse_date("2023-11-14T22:13:20", NOW), Some(NOW));
+    assert_eq!(parse_date("2024-02-29 00:00", NOW), Some(1_709_164_800));
+    assert_eq!(parse_date("2023-13-01", NOW), None);
+    assert_eq!(parse_date("2024-02-30", NOW), None);
+    assert_eq!(parse_date("2023-02-29", NOW), None);
+    assert_eq!(parse_date("2000-02-29", NOW), Some(951_782_400));
+    assert_eq!(parse_date("1900-02-29", NOW), None);
+    assert_eq!(parse_date("2024-04-31", NOW), None);
+    assert_eq!(parse_date("2024-05-01T-1:00", NOW), None);
+    assert_eq!(parse_date("2024-05-01T12:-30", NOW), None);
+    assert_eq!(parse_date("99999999999999999-01-01", NOW), None);
+}
+
+#[test]
+fn old_branches_are_removed_except_the_mainline() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+    test_repo.branch("old", test_repo.commits(root, 1, "old"));
+    let since = NOW + 10 * 60;
+    test_repo.branch("recent", test_repo.commits(root, 10, "recent"));
+
+    let mut branches = get_branches(&test_repo.repo).unwrap();
+    let commit_logs = get_commit_logs(&test_repo.repo, &branches).unwrap();
+    remove_inactive_branches(
+        &mut branches,
+        &MainlineBranches::default(),
+        &commit_logs,
+        since,
+    );
+
+    let names: Vec<_> = branches.iter().map(|bi| bi.name.as_str()).collect();
+    assert_eq!(names, vec!["main", "recent"]);
+}
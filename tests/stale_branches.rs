@@ -0,0 +1,30 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::{MainlineBranches, StaleBranch, get_branches, get_stale_branches};
+
+#[test]
+fn branches_forked_from_an_old_mainline_are_stale() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    let fork = test_repo.commits(root, 1, "main");
+    let main = test_repo.commits(fork, 2, "main");
+    test_repo.branch("main", main);
+    test_repo.branch("fresh", test_repo.commits(main, 1, "fresh"));
+    test_repo.branch("stale", test_repo.commits(fork, 1, "stale"));
+    let orphan = test_repo.commit(&[], "orphan");
+    test_repo.branch("orphan", orphan);
+
+    let branches = get_branches(&test_repo.repo).unwrap();
+    let stale =
+        get_stale_branches(&test_repo.repo, &branches, &MainlineBranches::default()).unwrap();
+
+    assert_eq!(
+        stale,
+        vec![StaleBranch {
+            name: "stale".to_string(),
+            behind: 2,
+        }]
+    );
+}
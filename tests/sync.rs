@@ -0,0 +1,129 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::sync::{SyncStep, continue_sync, plan_sync, sync};
+use git_stacked::{Error, MainlineBranches, get_branches, get_parent_of_relationships};
+
+// A stack feature -> child forked from main, with main moved since.
+fn stale_stack(main_files: &[(&str, &str)]) -> (TestRepo, git2::Oid) {
+    let test_repo = TestRepo::new();
+    test_repo.repo.set_head("refs/heads/main").unwrap();
+
+    let root = test_repo.commit(&[], "root");
+    let feature = test_repo.commit_files(root, &[("feature", "1")], "feature");
+    test_repo.branch("feature", feature);
+    test_repo.branch(
+        "child",
+        test_repo.commit_files(feature, &[("child", "1")], "child"),
+    );
+    let main = test_repo.commit_files(root, main_files, "main");
+    test_repo.branch("main", main);
+    test_repo
+        .repo
+        .checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+        .unwrap();
+
+    (test_repo, root)
+}
+
+#[test]
+fn stack_is_rebased_onto_mainline() {
+    let (test_repo, root) = stale_stack(&[("main", "1")]);
+    let repo = &test_repo.repo;
+
+    let steps = plan_sync(repo, &MainlineBranches::default()).unwrap();
+    let feature_oid = repo.revparse_single("feature").unwrap().id();
+    assert_eq!(
+        steps,
+        vec![
+            SyncStep {
+                branch: "feature".to_string(),
+                upstream_oid: root,
+                onto: "main".to_string(),
+            },
+            SyncStep {
+                branch: "child".to_string(),
+                upstream_oid: feature_oid,
+                onto: "feature".to_string(),
+            },
+        ]
+    );
+
+    let synced = sync(repo, &steps).unwrap();
+    assert_eq!(synced.len(), 2);
+    assert!(synced.iter().all(|rb| rb.commits == 1));
+
+    let branches = get_branches(repo).unwrap();
+    let parent_of = get_parent_of_relationships(repo, &branches).unwrap();
+    assert_eq!(parent_of.0["feature"], "main");
+    assert_eq!(parent_of.0["child"], "feature");
+}
+
+#[test]
+fn conflict_stops_the_sync_until_resolved() {
+    let (test_repo, root) = stale_stack(&[("feature", "2")]);
+    let repo = &test_repo.repo;
+
+    let steps = plan_sync(repo, &MainlineBranches::default()).unwrap();
+    match sync(repo, &steps) {
+        Err(Error::SyncConflict(branch, onto, upstream)) => {
+            assert_eq!(branch, "feature");
+            assert_eq!(onto, "main");
+            assert_eq!(upstream, root.to_string());
+        }
+        result => panic!("expected a conflict, got {:?}", result),
+    }
+
+    // Not resolved yet
+    assert!(matches!(continue_sync(repo), Err(Error::SyncConflict(..))));
+
+    // Resolve by hand by moving feature on top of main
+    let main = repo.revparse_single("main").unwrap().id();
+    let resolved = test_repo.commit_files(main, &[("feature", "3")], "feature resolved");
+    test_repo.branch("feature", resolved);
+
+    let synced = continue_sync(repo).unwrap();
+    assert_eq!(synced.len(), 1);
+    assert_eq!(synced[0].name, "child");
+
+    let branches = get_branches(repo).unwrap();
+    let parent_of = get_parent_of_relationships(repo, &branches).unwrap();
+    assert_eq!(parent_of.0["child"], "feature");
+    assert!(matches!(continue_sync(repo), Err(Error::NoSyncInProgress)));
+}
+
+#[test]
+fn merged_root_is_left_alone() {
+    let test_repo = TestRepo::new();
+    test_repo.repo.set_head("refs/heads/main").unwrap();
+
+    let root = test_repo.commit(&[], "root");
+    let old = test_repo.commit_files(root, &[("old", "1")], "old");
+    test_repo.branch("old", old);
+    let main = test_repo.commit_files(old, &[("main", "1")], "main");
+    test_repo.branch("main", main);
+    test_repo.branch(
+        "feature",
+        test_repo.commit_files(root, &[("feature", "1")], "feature"),
+    );
+    test_repo
+        .repo
+        .checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+        .unwrap();
+
+    let repo = &test_repo.repo;
+    let steps = plan_sync(repo, &MainlineBranches::default()).unwrap();
+    assert_eq!(
+        steps,
+        vec![SyncStep {
+            branch: "feature".to_string(),
+            upstream_oid: root,
+            onto: "main".to_string(),
+        }]
+    );
+
+    sync(repo, &steps).unwrap();
+    assert_eq!(repo.revparse_single("old").unwrap().id(), old);
+    assert_eq!(repo.revparse_single("main").unwrap().id(), main);
+    assert!(matches!(continue_sync(repo), Err(Error::NoSyncInProgress)));
+}
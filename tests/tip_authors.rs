@@ -0,0 +1,28 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::{get_branches, get_tip_authors};
+use git2::Signature;
+
+#[test]
+fn authors_of_the_tips_are_returned() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    test_repo.branch("main", root);
+
+    let signature = Signature::now("Ann", "ann@example.com").unwrap();
+    let tree = test_repo.repo.find_commit(root).unwrap().tree().unwrap();
+    let parent = test_repo.repo.find_commit(root).unwrap();
+    let anns = test_repo
+        .repo
+        .commit(None, &signature, &signature, "ann", &tree, &[&parent])
+        .unwrap();
+    test_repo.branch("anns", anns);
+
+    let branches = get_branches(&test_repo.repo).unwrap();
+    let authors = get_tip_authors(&test_repo.repo, &branches).unwrap();
+
+    assert_eq!(authors.0["anns"], "Ann");
+    assert_eq!(authors.0["main"], "test");
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use common::TestRepo;
+use git_stacked::{
+    MainlineBranches, build_children_and_roots, get_branches, get_parent_of_relationships,
+    get_topo_order,
+};
+
+#[test]
+fn siblings_are_ordered_by_fork_point() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    let main = test_repo.commits(root, 2, "main");
+    test_repo.branch("main", main);
+    // zeta gets its first commit before alpha but its tip is the most recent
+    let zeta = test_repo.commits(main, 1, "zeta");
+    test_repo.branch("alpha", test_repo.commits(main, 1, "alpha"));
+    test_repo.branch("zeta", test_repo.commits(zeta, 2, "zeta"));
+
+    let branches = get_branches(&test_repo.repo).unwrap();
+    let parent_of = get_parent_of_relationships(&test_repo.repo, &branches).unwrap();
+    let mut stack = build_children_and_roots(&branches, &parent_of).unwrap();
+    assert_eq!(stack.children_map.0["main"], vec!["alpha", "zeta"]);
+
+    let mainline = MainlineBranches::default();
+    let topo_order = get_topo_order(&test_repo.repo, &branches, &parent_of, &mainline).unwrap();
+    stack.sort_by_key(|name| topo_order.0[name]);
+    assert_eq!(stack.children_map.0["main"], vec!["zeta", "alpha"]);
+}
+
+#[test]
+fn roots_fork_at_their_merge_base_with_the_mainline() {
+    let test_repo = TestRepo::new();
+
+    let root = test_repo.commit(&[], "root");
+    let early = test_repo.commits(root, 1, "main");
+    let old = test_repo.commits(early, 1, "old");
+    let main = test_repo.commits(early, 2, "main");
+    test_repo.branch("main", main);
+    test_repo.branch("old", test_repo.commits(old, 3, "old"));
+
+    let branches = get_branches(&test_repo.repo).unwrap();
+    let parent_of = get_parent_of_relationships(&test_repo.repo, &branches).unwrap();
+    assert!(!parent_of.0.contains_key("old"));
+
+    let mainline = MainlineBranches::default();
+    let topo_order = get_topo_order(&test_repo.repo, &branches, &parent_of, &mainline).unwrap();
+    let old_first = test_repo.repo.find_commit(old).unwrap().time().seconds();
+    assert_eq!(topo_order.0["old"].0, old_first);
+}